@@ -0,0 +1,129 @@
+mod wav;
+pub use wav::WavAudio;
+
+#[cfg(feature = "audio")]
+mod speaker;
+#[cfg(feature = "audio")]
+pub use speaker::Speaker;
+
+use crate::chip8::{Platform, State};
+
+// A square wave, as an XO-CHIP audio pattern.
+const BEEPER_PATTERN: [u8; 16] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+const PATTERN_BITS: f32 = 128.0;
+
+// Where the sound goes, e.g. speakers or a file.
+pub trait AudioOutput {
+    // Plays the tone, or silence, until the next call. Called once per 60 Hz frame.
+    fn play_frame(&mut self, tone: Option<Tone>);
+}
+
+// Throws the sound away.
+pub struct NullAudio;
+
+impl AudioOutput for NullAudio {
+    fn play_frame(&mut self, _: Option<Tone>) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub pattern: [u8; 16], // 1-bit samples, highest bit first.
+    pub rate: f32,         // Pattern bits per second.
+    pub volume: f32,
+}
+
+pub struct AudioSettings {
+    pub muted: bool,
+    pub volume: f32, // From 0 to 1.
+    pub pitch: f32,  // Beeper frequency in Hz.
+}
+
+impl AudioSettings {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            muted: false,
+            volume: 0.25,
+            pitch: 440.0,
+        }
+    }
+
+    // What should be heard while the sound timer is running.
+    #[must_use]
+    pub fn tone(&self, chip8_state: &State) -> Option<Tone> {
+        if self.muted || chip8_state.sound() == 0 {
+            return None;
+        }
+        let tone = match chip8_state.audio_pattern() {
+            Some(pattern) if chip8_state.platform() >= Platform::XoChip => Tone {
+                pattern,
+                rate: 4000.0 * 2.0_f32.powf((f32::from(chip8_state.pitch()) - 64.0) / 48.0),
+                volume: self.volume,
+            },
+            _ => Tone {
+                pattern: BEEPER_PATTERN,
+                rate: self.pitch * PATTERN_BITS,
+                volume: self.volume,
+            },
+        };
+        Some(tone)
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Turns tones into samples, carrying on smoothly from one frame to the next.
+pub struct ToneGenerator {
+    sample_rate: f32,
+    position: f32, // Bit of the pattern we're at.
+}
+
+impl ToneGenerator {
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate as f32,
+            position: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self, tone: Option<&Tone>) -> f32 {
+        let Some(tone) = tone else {
+            self.position = 0.0;
+            return 0.0;
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let bit = self.position as usize;
+        self.position = (self.position + tone.rate / self.sample_rate) % PATTERN_BITS;
+        if tone.pattern[bit / 8] << (bit % 8) & 0b1000_0000 != 0 {
+            tone.volume
+        } else {
+            -tone.volume
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beeper_square_wave() {
+        let tone = Tone {
+            pattern: BEEPER_PATTERN,
+            rate: 1000.0 * PATTERN_BITS, // 1 kHz
+            volume: 0.5,
+        };
+        let mut generator = ToneGenerator::new(4000);
+        let samples: Vec<f32> = (0..8).map(|_| generator.next_sample(Some(&tone))).collect();
+        assert_eq!(vec![0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5], samples);
+        assert!(generator.next_sample(None).abs() < f32::EPSILON);
+    }
+}
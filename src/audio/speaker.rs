@@ -0,0 +1,63 @@
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample, Stream, StreamConfig};
+
+use super::{AudioOutput, Tone, ToneGenerator};
+
+// Plays the sound on the default output device.
+pub struct Speaker {
+    tone: Arc<Mutex<Option<Tone>>>, // Shared with the audio thread.
+    _stream: Stream,
+}
+
+impl Speaker {
+    // Returns None if there's no usable output device.
+    #[must_use]
+    pub fn new() -> Option<Self> {
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?;
+        let tone = Arc::new(Mutex::new(None));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config.into(), &tone),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config.into(), &tone),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config.into(), &tone),
+            _ => None,
+        }?;
+        stream.play().ok()?;
+        Some(Self {
+            tone,
+            _stream: stream,
+        })
+    }
+}
+
+impl AudioOutput for Speaker {
+    fn play_frame(&mut self, tone: Option<Tone>) {
+        *self.tone.lock().unwrap() = tone;
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    tone: &Arc<Mutex<Option<Tone>>>,
+) -> Option<Stream> {
+    let channels = config.channels as usize;
+    let mut generator = ToneGenerator::new(config.sample_rate.0);
+    let tone = Arc::clone(tone);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let tone = *tone.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    let sample = T::from_sample(generator.next_sample(tone.as_ref()));
+                    frame.fill(sample);
+                }
+            },
+            |error| eprintln!("audio stream error: {error}"),
+            None,
+        )
+        .ok()
+}
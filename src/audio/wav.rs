@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{AudioOutput, Tone, ToneGenerator};
+
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / 60;
+const HEADER_SIZE: u32 = 44;
+
+// Records the sound to a 16-bit mono WAV file.
+pub struct WavAudio {
+    file: BufWriter<File>,
+    generator: ToneGenerator,
+    samples_written: u32,
+    error: Option<io::Error>, // The first write that failed, reported by finish().
+}
+
+impl WavAudio {
+    /// # Errors
+    ///
+    /// Fails if the file can't be created or written to.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // the sizes get filled in by finish()
+        write_header(&mut file, 0)?;
+        Ok(Self {
+            file,
+            generator: ToneGenerator::new(SAMPLE_RATE),
+            samples_written: 0,
+            error: None,
+        })
+    }
+
+    /// Fills in the header now that the length is known.
+    ///
+    /// # Errors
+    ///
+    /// Fails if writing any of the audio or the header failed.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.file, self.samples_written)?;
+        self.file.flush()
+    }
+
+    fn write_frame(&mut self, tone: Option<&Tone>) -> io::Result<()> {
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = self.generator.next_sample(tone);
+            #[allow(clippy::cast_possible_truncation)]
+            let sample = (sample * f32::from(i16::MAX)) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples_written += SAMPLES_PER_FRAME;
+        Ok(())
+    }
+}
+
+impl AudioOutput for WavAudio {
+    fn play_frame(&mut self, tone: Option<Tone>) {
+        if self.error.is_none() {
+            self.error = self.write_frame(tone.as_ref()).err();
+        }
+    }
+}
+
+fn write_header(file: &mut impl Write, samples: u32) -> io::Result<()> {
+    let data_size = samples * 2;
+    file.write_all(b"RIFF")?;
+    file.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?; // size of the fmt chunk
+    file.write_all(&1u16.to_le_bytes())?; // PCM
+    file.write_all(&1u16.to_le_bytes())?; // mono
+    file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    file.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+    file.write_all(&2u16.to_le_bytes())?; // bytes per sample
+    file.write_all(&16u16.to_le_bytes())?; // bits per sample
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())
+}
@@ -0,0 +1,463 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::{env, process};
+
+use chip8uwu::audio::{AudioOutput, AudioSettings, WavAudio};
+use chip8uwu::chip8::{
+    self, Chip8Error, Disassembly, Halt, Movie, Platform, Preset, ROM_START, State,
+};
+
+mod png;
+
+const USAGE: &str = "\
+usage: chip8uwu-cli <rom> [options]
+       chip8uwu-cli disassemble <rom> [--platform <name>] [--output <file>]
+       chip8uwu-cli assemble <source> [--output <file>]
+  --frames <n>       frames to run at 60 Hz (default 600, or the movie's length)
+  --cycles <n>       instructions per frame (default 10)
+  --platform <name>  chip8, schip or xochip (default chip8)
+  --quirks <name>    vip, chip48, schip or modern (default modern)
+  --keys <file>      key script, lines of `<frame> <held keys in hex>...`
+  --movie <file>     play back a movie recorded in the gui, with its settings
+  --seed <n>         seed for the random numbers, random by default
+  --until-halt       stop early when the rom exits or faults
+  --dump <format>    framebuffer as ascii (default), png or none
+  --output <file>    where the framebuffer goes, stdout by default
+  --scale <n>        png pixels per CHIP-8 pixel, 1 to 64 (default 1)
+  --wav <file>       record the sound
+disassemble writes an assembly listing of the rom that assembles back to it,
+assemble turns one back into a rom. Roms ending in .8o are Octo source and get
+compiled before running.";
+
+// Characters for pixels that are on in no plane, plane 1, plane 2 and both planes.
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+// Same for png, in grayscale.
+const GRAY_PIXELS: [u8; 4] = [0x00, 0xff, 0x55, 0xaa];
+// Png scales that keep even a 128x64 screen's png a sane size.
+const SCALES: std::ops::RangeInclusive<usize> = 1..=64;
+
+#[derive(PartialEq)]
+enum Dump {
+    Ascii,
+    Png,
+    None,
+}
+
+struct Options {
+    rom: String,
+    frames: Option<u64>,
+    cycles_per_frame: usize,
+    platform: Platform,
+    preset: Preset,
+    keys: Option<String>,
+    movie: Option<String>,
+    seed: Option<u64>,
+    until_halt: bool,
+    dump: Dump,
+    output: Option<String>,
+    scale: usize,
+    wav: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            rom: String::new(),
+            frames: None,
+            cycles_per_frame: 10,
+            platform: Platform::Chip8,
+            preset: Preset::Modern,
+            keys: None,
+            movie: None,
+            seed: None,
+            until_halt: false,
+            dump: Dump::Ascii,
+            output: None,
+            scale: 1,
+            wav: None,
+        };
+        let mut rom = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--frames" => options.frames = Some(parse_number(arg, &value()?)?),
+                "--cycles" => options.cycles_per_frame = parse_number(arg, &value()?)?,
+                "--platform" => options.platform = parse_platform(&value()?)?,
+                "--quirks" => {
+                    options.preset = match value()?.as_str() {
+                        "vip" => Preset::CosmacVip,
+                        "chip48" => Preset::Chip48,
+                        "schip" => Preset::SuperChip,
+                        "modern" => Preset::Modern,
+                        other => return Err(format!("unknown quirks: {other}")),
+                    }
+                }
+                "--keys" => options.keys = Some(value()?),
+                "--movie" => options.movie = Some(value()?),
+                "--seed" => options.seed = Some(parse_number(arg, &value()?)?),
+                "--until-halt" => options.until_halt = true,
+                "--dump" => {
+                    options.dump = match value()?.as_str() {
+                        "ascii" => Dump::Ascii,
+                        "png" => Dump::Png,
+                        "none" => Dump::None,
+                        other => return Err(format!("unknown dump format: {other}")),
+                    }
+                }
+                "--output" => options.output = Some(value()?),
+                "--scale" => options.scale = parse_number(arg, &value()?)?,
+                "--wav" => options.wav = Some(value()?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+                _ if rom.is_none() => rom = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument: {arg}")),
+            }
+        }
+        options.rom = rom.ok_or("no rom given")?;
+        if options.dump == Dump::Png && options.output.is_none() {
+            return Err(String::from("png dumps need --output"));
+        }
+        if !SCALES.contains(&options.scale) {
+            return Err(format!(
+                "--scale needs to be from {} to {}",
+                SCALES.start(),
+                SCALES.end()
+            ));
+        }
+        if options.movie.is_some() && (options.keys.is_some() || options.seed.is_some()) {
+            return Err(String::from("movies have their own keys and seed"));
+        }
+        Ok(options)
+    }
+}
+
+// Options for assemble and disassemble.
+struct SourceOptions {
+    input: String,
+    platform: Platform,
+    output: Option<String>,
+}
+
+impl SourceOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut input = None;
+        // decodes everything, older roms just don't use the newer instructions
+        let mut platform = Platform::XoChip;
+        let mut output = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--platform" => platform = parse_platform(&value()?)?,
+                "--output" => output = Some(value()?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+                _ if input.is_none() => input = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument: {arg}")),
+            }
+        }
+        Ok(SourceOptions {
+            input: input.ok_or("no input file given")?,
+            platform,
+            output,
+        })
+    }
+}
+
+fn parse_platform(value: &str) -> Result<Platform, String> {
+    match value {
+        "chip8" => Ok(Platform::Chip8),
+        "schip" => Ok(Platform::SuperChip),
+        "xochip" => Ok(Platform::XoChip),
+        other => Err(format!("unknown platform: {other}")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{arg} needs a number, got {value}"))
+}
+
+// Keys held from a frame on. "120 5 6" holds 5 and 6 from frame 120 on,
+// "180" on its own lets go of everything. Everything after a # is a comment.
+fn parse_key_script(script: &str) -> Result<Vec<(u64, [bool; 16])>, String> {
+    let mut changes: Vec<(u64, [bool; 16])> = Vec::new();
+    for (line_number, line) in (1..).zip(script.lines()) {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(frame) = words.next() else {
+            continue;
+        };
+        let frame = frame
+            .parse()
+            .map_err(|_| format!("line {line_number}: bad frame number {frame}"))?;
+        if changes.last().is_some_and(|&(last, _)| last > frame) {
+            return Err(format!("line {line_number}: frames have to be in order"));
+        }
+        let mut keys = [false; 16];
+        for key in words {
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or(format!("line {line_number}: bad key {key}"))?;
+            keys[key as usize] = true;
+        }
+        changes.push((frame, keys));
+    }
+    Ok(changes)
+}
+
+// Runs a rom without the gui, exiting with 1 if it faulted and 2 for bad arguments.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(command @ ("assemble" | "disassemble")) = args.first().map(String::as_str) {
+        let options = SourceOptions::parse(&args[1..]).unwrap_or_else(|error| {
+            eprintln!("{error}\n{USAGE}");
+            process::exit(2);
+        });
+        let result = if command == "assemble" {
+            assemble(&options)
+        } else {
+            disassemble(&options)
+        };
+        if let Err(error) = result {
+            eprintln!("{error}");
+            process::exit(1);
+        }
+        return;
+    }
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}\n{USAGE}");
+            process::exit(2);
+        }
+    };
+    match run(&options) {
+        Ok(state) if matches!(state.halted(), Some(Halt::Fault(_))) => process::exit(1),
+        Ok(_) => {}
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(1);
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<State, Box<dyn Error>> {
+    // .8o files are Octo source, compiled first
+    let rom = if Path::new(&options.rom)
+        .extension()
+        .is_some_and(|extension| extension == "8o")
+    {
+        chip8::compile_octo(&fs::read_to_string(&options.rom)?)?
+    } else {
+        fs::read(&options.rom)?
+    };
+    let key_script = match &options.keys {
+        Some(path) => parse_key_script(&fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let mut wav = options.wav.as_ref().map(WavAudio::create).transpose()?;
+    let audio_settings = AudioSettings::new();
+
+    let movie = match &options.movie {
+        Some(path) => Some(Movie::load(&fs::read(path)?)?),
+        None => None,
+    };
+
+    let mut chip8_state = State::with_quirks(options.preset.quirks());
+    chip8_state.set_platform(options.platform);
+    let mut cycles_per_frame = options.cycles_per_frame;
+    let mut frames = options.frames.unwrap_or(600);
+    if let Some(movie) = &movie {
+        movie.start(&mut chip8_state, &rom)?;
+        cycles_per_frame = movie.cycles_per_frame;
+        frames = options.frames.unwrap_or(movie.frames.len() as u64);
+    } else {
+        chip8_state.load_rom(&rom)?;
+    }
+    if let Some(seed) = options.seed {
+        chip8_state.reseed(seed);
+    }
+
+    let mut key_changes = key_script.into_iter().peekable();
+    for frame in 0..frames {
+        while let Some((_, keys)) = key_changes.next_if(|&(start, _)| start <= frame) {
+            chip8_state.keyboard = keys;
+        }
+        // everything's let go once the movie's over
+        if let Some(movie) = &movie
+            && !movie.play_frame(&mut chip8_state)
+        {
+            chip8_state.keyboard = [false; 16];
+        }
+        // faults halt the cpu, they're reported along with the registers
+        let _ = chip8_state.run_frame(cycles_per_frame);
+        if let Some(wav) = &mut wav {
+            wav.play_frame(audio_settings.tone(&chip8_state));
+        }
+        if options.until_halt && chip8_state.halted().is_some() {
+            break;
+        }
+    }
+    if let Some(wav) = wav {
+        wav.finish()?;
+    }
+
+    print_registers(&chip8_state);
+    match options.dump {
+        Dump::Ascii => write_ascii(&mut output(options.output.as_deref())?, &chip8_state)?,
+        Dump::Png => write_png(
+            &mut output(options.output.as_deref())?,
+            &chip8_state,
+            options.scale,
+        )?,
+        Dump::None => {}
+    }
+    Ok(chip8_state)
+}
+
+fn assemble(options: &SourceOptions) -> Result<(), Box<dyn Error>> {
+    let rom = chip8::assemble(&fs::read_to_string(&options.input)?)?;
+    let mut out = output(options.output.as_deref())?;
+    out.write_all(&rom)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn disassemble(options: &SourceOptions) -> Result<(), Box<dyn Error>> {
+    let disassembly = disassemble_rom(&fs::read(&options.input)?, options.platform)?;
+    let name = Path::new(&options.input)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut out = output(options.output.as_deref())?;
+    out.write_all(disassembly.export(&name).as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+// Fails like State::load_rom for roms that don't fit in the platform's memory.
+fn disassemble_rom(rom: &[u8], platform: Platform) -> Result<Disassembly, Chip8Error> {
+    let capacity = platform.memory_size() - ROM_START as usize;
+    if rom.len() > capacity {
+        return Err(Chip8Error::RomTooLarge {
+            size: rom.len(),
+            capacity,
+        });
+    }
+    Ok(Disassembly::new(rom, ROM_START, platform, &[ROM_START]))
+}
+
+fn output(path: Option<&str>) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+fn print_registers(chip8_state: &State) {
+    println!(
+        "PC ${:04x}  I ${:04x}  SP {}  DELAY {:02x}  SOUND {:02x}",
+        chip8_state.pc,
+        chip8_state.i(),
+        chip8_state.sp(),
+        chip8_state.delay,
+        chip8_state.sound()
+    );
+    let registers: Vec<String> = (0..)
+        .zip(chip8_state.v)
+        .map(|(v, value)| format!("V{v:X} {value:02x}"))
+        .collect();
+    println!("{}", registers.join("  "));
+    if let Some(halt) = chip8_state.halted() {
+        println!("halted: {halt}");
+    }
+}
+
+fn write_ascii(out: &mut impl Write, chip8_state: &State) -> io::Result<()> {
+    let display = &chip8_state.display;
+    for row in display.frame().chunks_exact(display.width()) {
+        let line: String = row
+            .iter()
+            .map(|&pixel| ASCII_PIXELS[pixel as usize])
+            .collect();
+        writeln!(out, "{line}")?;
+    }
+    out.flush()
+}
+
+fn write_png(out: &mut impl Write, chip8_state: &State, scale: usize) -> io::Result<()> {
+    let display = &chip8_state.display;
+    let (width, height) = (display.width() * scale, display.height() * scale);
+    let frame = display.frame();
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = frame[y / scale * display.width() + x / scale];
+            pixels.push(GRAY_PIXELS[pixel as usize]);
+        }
+    }
+    png::write_grayscale(out, width, height, &pixels)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_script() {
+        let script = "\
+            # press 5, then 5 and a
+            10 5
+            20 5 A # both
+            30
+        ";
+        let changes = parse_key_script(script).unwrap();
+        assert_eq!(
+            vec![10, 20, 30],
+            changes.iter().map(|&(frame, _)| frame).collect::<Vec<_>>()
+        );
+        assert!(changes[1].1[0x5] && changes[1].1[0xa]);
+        assert_eq!([false; 16], changes[2].1);
+        assert_eq!(
+            Err(String::from("line 2: bad key 10")),
+            parse_key_script("1 5\n2 10")
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_scale() {
+        for (scale, ok) in [("0", false), ("1", true), ("64", true), ("65", false)] {
+            let args = [
+                "game.ch8",
+                "--dump",
+                "png",
+                "--output",
+                "screen.png",
+                "--scale",
+                scale,
+            ];
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            let error = (!ok).then(|| String::from("--scale needs to be from 1 to 64"));
+            assert_eq!(error, Options::parse(&args).err(), "--scale {scale}");
+        }
+    }
+
+    #[test]
+    fn disassemble_rejects_large_roms() {
+        let rom = vec![0x00; 0x10000];
+        assert_eq!(
+            Some(Chip8Error::RomTooLarge {
+                size: 0x10000,
+                capacity: 0xfe00,
+            }),
+            disassemble_rom(&rom, Platform::XoChip).err()
+        );
+        assert!(disassemble_rom(&rom[..0xe00], Platform::Chip8).is_ok());
+        assert!(disassemble_rom(&rom[..0xe01], Platform::Chip8).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use super::op::Op;
+use super::state::ROM_START;
+
+// Something wrong with the source, on a line counting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// Assembles source written with the mnemonics `Op::disassemble` prints into a rom that
+/// `State::load_rom` can load. Besides instructions, a line can have:
+/// - a label: `loop:`, on its own or in front of a statement
+/// - a constant: `SPEED equ 4` or `SPEED = 4`
+/// - bytes or words: `db $ff, %10000001, 3` and `dw $1234`, words are big-endian
+///
+/// Numbers are decimal, or hex after $ or 0x, or binary after % or 0b, optionally with a # in
+/// front like in `MVI V0, #$10`. Labels and constants can be added to and subtracted from them.
+/// Everything after a ; is a comment.
+///
+/// # Errors
+///
+/// Fails on the first line that doesn't assemble, with its line number.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let (statements, symbols) = parse(source)?;
+    let mut rom = Vec::new();
+    for statement in &statements {
+        let error = |message| AssemblyError {
+            line: statement.line,
+            message,
+        };
+        match &statement.kind {
+            Kind::Instruction { mnemonic, args } => {
+                let operands = args
+                    .iter()
+                    .map(|arg| symbols.operand(arg))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                rom.extend(encode(mnemonic, &operands).map_err(error)?.encode());
+            }
+            Kind::Bytes(args) => {
+                for arg in args {
+                    let value = symbols.value(arg, 0).map_err(error)?;
+                    rom.push(fit_byte(value).map_err(error)?);
+                }
+            }
+            Kind::Words(args) => {
+                for arg in args {
+                    let value = symbols.value(arg, 0).map_err(error)?;
+                    rom.extend(fit_word(value).map_err(error)?.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(rom)
+}
+
+struct Statement<'a> {
+    line: usize,
+    kind: Kind<'a>,
+}
+
+enum Kind<'a> {
+    Instruction {
+        mnemonic: String,
+        args: Vec<&'a str>,
+    },
+    Bytes(Vec<&'a str>),
+    Words(Vec<&'a str>),
+}
+
+impl Kind<'_> {
+    fn size(&self) -> usize {
+        match self {
+            Kind::Instruction { mnemonic, .. } if mnemonic == "MVI.L" => 4,
+            Kind::Instruction { .. } => 2,
+            Kind::Bytes(args) => args.len(),
+            Kind::Words(args) => args.len() * 2,
+        }
+    }
+}
+
+// The first pass: splits lines into statements and finds out where every label is.
+fn parse(source: &str) -> Result<(Vec<Statement<'_>>, Symbols<'_>), AssemblyError> {
+    let mut statements = Vec::new();
+    let mut symbols = Symbols::default();
+    let mut address = usize::from(ROM_START);
+    for (line, text) in (1..).zip(source.lines()) {
+        let error = |message| AssemblyError { line, message };
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+
+        if let Some((label, rest)) = text.split_once(':')
+            && is_symbol(label.trim())
+        {
+            let value = u16::try_from(address)
+                .map_err(|_| error(String::from("the program doesn't fit in memory")))?;
+            symbols
+                .define(label.trim(), Symbol::Label(value))
+                .map_err(error)?;
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (first, rest) = split_word(text);
+        let (second, value) = split_word(rest);
+        if second.eq_ignore_ascii_case("equ") || rest.starts_with('=') {
+            let value = if second.eq_ignore_ascii_case("equ") {
+                value
+            } else {
+                rest[1..].trim()
+            };
+            if !is_symbol(first) {
+                return Err(error(format!("\"{first}\" can't be a constant's name")));
+            }
+            symbols
+                .define(first, Symbol::Constant { value, line })
+                .map_err(error)?;
+            continue;
+        }
+
+        let args: Vec<&str> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+        let kind = match first.to_ascii_uppercase().as_str() {
+            "DB" => Kind::Bytes(args),
+            "DW" => Kind::Words(args),
+            mnemonic => Kind::Instruction {
+                mnemonic: mnemonic.to_string(),
+                args,
+            },
+        };
+        address += kind.size();
+        statements.push(Statement { line, kind });
+    }
+    if address > 0x10000 {
+        return Err(AssemblyError {
+            line: statements.last().map_or(1, |statement| statement.line),
+            message: String::from("the program doesn't fit in memory"),
+        });
+    }
+    Ok((statements, symbols))
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '.')
+}
+
+#[derive(Clone, Copy)]
+enum Symbol<'a> {
+    Label(u16),
+    Constant { value: &'a str, line: usize }, // Worked out when it's used.
+}
+
+#[derive(Default)]
+struct Symbols<'a> {
+    symbols: HashMap<&'a str, Symbol<'a>>,
+}
+
+// What an instruction's argument can be.
+#[derive(Clone, Copy)]
+enum Operand {
+    V(u8),
+    I,
+    IndirectI, // (I)
+    Delay,
+    Sound,
+    Flags,         // R, SUPER-CHIP's flag registers
+    Range(u8, u8), // Vx-Vy
+    PlusV0(i64),   // address(V0)
+    Value(i64),
+}
+
+impl<'a> Symbols<'a> {
+    fn define(&mut self, name: &'a str, symbol: Symbol<'a>) -> Result<(), String> {
+        if register(name).is_some() || keyword(name).is_some() {
+            return Err(format!("\"{name}\" is a register"));
+        }
+        if self.symbols.insert(name, symbol).is_some() {
+            return Err(format!("\"{name}\" is defined more than once"));
+        }
+        Ok(())
+    }
+
+    fn operand(&self, arg: &str) -> Result<Operand, String> {
+        if let Some(v) = register(arg) {
+            return Ok(Operand::V(v));
+        }
+        if let Some(operand) = keyword(arg) {
+            return Ok(operand);
+        }
+        if let Some((first, last)) = arg.split_once('-')
+            && let (Some(first), Some(last)) = (register(first.trim()), register(last.trim()))
+        {
+            return Ok(Operand::Range(first, last));
+        }
+        if let Some(address) = arg.strip_suffix("(V0)").or(arg.strip_suffix("(v0)")) {
+            return Ok(Operand::PlusV0(self.value(address, 0)?));
+        }
+        Ok(Operand::Value(self.value(arg, 0)?))
+    }
+
+    // Adds and subtracts the numbers and symbols in an expression like `data + 2`.
+    // depth counts constants defined in terms of other constants, to catch loops.
+    fn value(&self, expression: &str, depth: usize) -> Result<i64, String> {
+        if expression.trim().is_empty() {
+            return Err(String::from("expected a value"));
+        }
+        let mut total: i64 = 0;
+        let mut sign: i64 = 1;
+        let mut term = String::new();
+        for char in expression.chars().chain(['+']) {
+            match char {
+                '+' | '-' => {
+                    let term = std::mem::take(&mut term);
+                    let term = term.trim();
+                    if !term.is_empty() {
+                        total = sign
+                            .checked_mul(self.term(term, depth)?)
+                            .and_then(|term| total.checked_add(term))
+                            .ok_or(format!("\"{}\" is too large", expression.trim()))?;
+                        sign = 1;
+                    }
+                    if char == '-' {
+                        sign = -sign;
+                    }
+                }
+                _ => term.push(char),
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, depth: usize) -> Result<i64, String> {
+        let number = term.strip_prefix('#').unwrap_or(term);
+        let parsed = if let Some(hex) = number.strip_prefix('$').or(number.strip_prefix("0x")) {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(binary) = number.strip_prefix('%').or(number.strip_prefix("0b")) {
+            i64::from_str_radix(binary, 2)
+        } else if number.starts_with(|char: char| char.is_ascii_digit()) {
+            number.parse()
+        } else {
+            return self.symbol(number, depth);
+        };
+        parsed.map_err(|_| format!("\"{term}\" isn't a number"))
+    }
+
+    fn symbol(&self, name: &str, depth: usize) -> Result<i64, String> {
+        match self.symbols.get(name) {
+            Some(Symbol::Label(address)) => Ok(i64::from(*address)),
+            Some(Symbol::Constant { value, line }) => {
+                if depth > 64 {
+                    return Err(format!("\"{name}\" is defined in terms of itself"));
+                }
+                self.value(value, depth + 1)
+                    .map_err(|message| format!("{message}, in {name} on line {line}"))
+            }
+            None => Err(format!("\"{name}\" isn't defined")),
+        }
+    }
+}
+
+fn register(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix('V').or(name.strip_prefix('v'))?;
+    let mut digits = digit.chars();
+    let v = digits.next()?.to_digit(16)?;
+    digits.next().is_none().then(|| u8::try_from(v).unwrap())
+}
+
+fn keyword(name: &str) -> Option<Operand> {
+    match name.to_ascii_uppercase().as_str() {
+        "I" => Some(Operand::I),
+        "(I)" => Some(Operand::IndirectI),
+        "DELAY" => Some(Operand::Delay),
+        "SOUND" => Some(Operand::Sound),
+        "R" => Some(Operand::Flags),
+        _ => None,
+    }
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<Op, String> {
+    use Operand::{Delay, Flags, I, IndirectI, PlusV0, Range, Sound, V, Value};
+    let op = match (mnemonic, operands) {
+        ("CLS", []) => Op::Cls,
+        ("RTS", []) => Op::Rts,
+        ("SCROLL.D", &[Value(n)]) => Op::ScrollDown(fit_nibble(n)?),
+        ("SCROLL.U", &[Value(n)]) => Op::ScrollUp(fit_nibble(n)?),
+        ("SCROLL.R", []) => Op::ScrollRight,
+        ("SCROLL.L", []) => Op::ScrollLeft,
+        ("EXIT", []) => Op::Exit,
+        ("LORES", []) => Op::Lores,
+        ("HIRES", []) => Op::Hires,
+        ("JUMP", &[Value(address)]) => Op::Jump(fit_address(address)?),
+        ("JUMP", &[PlusV0(address)]) => Op::JumpPlusV0(fit_address(address)?),
+        ("CALL", &[Value(address)]) => Op::Call(fit_address(address)?),
+        ("SKIP.EQ", &[V(v), V(v2)]) => Op::SkipEq { v, v2 },
+        ("SKIP.EQ", &[V(v), Value(lit)]) => Op::SkipEqLit {
+            v,
+            lit: fit_byte(lit)?,
+        },
+        ("SKIP.NE", &[V(v), V(v2)]) => Op::SkipNe { v, v2 },
+        ("SKIP.NE", &[V(v), Value(lit)]) => Op::SkipNeLit {
+            v,
+            lit: fit_byte(lit)?,
+        },
+        ("MVI", &[V(v), Value(lit)]) => Op::MviLit {
+            v,
+            lit: fit_byte(lit)?,
+        },
+        ("MVI", &[I, Value(address)]) => Op::SetI(fit_address(address)?),
+        ("MVI.L", &[I, Value(address)]) => Op::LongSetI(fit_word(address)?),
+        ("ADI", &[V(v), Value(lit)]) => Op::AdiLit {
+            v,
+            lit: fit_byte(lit)?,
+        },
+        ("RNDMSK", &[V(v), Value(lit)]) => Op::Rand {
+            v,
+            lit: fit_byte(lit)?,
+        },
+        ("MOV", &[V(v), V(v2)]) => Op::Mov { v, v2 },
+        ("MOV", &[V(v), Delay]) => Op::GetDelay(v),
+        ("MOV", &[Delay, V(v)]) => Op::Delay(v),
+        ("MOV", &[Sound, V(v)]) => Op::Sound(v),
+        ("OR", &[V(v), V(v2)]) => Op::Or { v, v2 },
+        ("AND", &[V(v), V(v2)]) => Op::And { v, v2 },
+        ("XOR", &[V(v), V(v2)]) => Op::Xor { v, v2 },
+        ("ADD.", &[V(v), V(v2)]) => Op::Add { v, v2 },
+        ("SUB.", &[V(v), V(v2)]) => Op::Sub { v, v2 },
+        ("SUBB.", &[V(v), V(v2)]) => Op::Subb { v, v2 },
+        ("SHR.", &[V(v), V(v2)]) => Op::Shr { v, v2 },
+        ("SHL.", &[V(v), V(v2)]) => Op::Shl { v, v2 },
+        ("ADD", &[I, V(v)]) => Op::AddI(v),
+        ("SPRITE", &[V(v), V(v2), Value(lit)]) => Op::Draw {
+            v,
+            v2,
+            lit: fit_nibble(lit)?,
+        },
+        ("SKIP.KEY", &[V(v)]) => Op::SkipKey(v),
+        ("SKIP.NOKEY", &[V(v)]) => Op::SkipNoKey(v),
+        ("WAITKEY", &[V(v)]) => Op::GetKey(v),
+        ("SPRITECHAR", &[V(v)]) => Op::SpriteChar(v),
+        ("BIGCHAR", &[V(v)]) => Op::BigSpriteChar(v),
+        ("MOVBCD", &[V(v)]) => Op::MovBcd(v),
+        ("PITCH", &[V(v)]) => Op::Pitch(v),
+        ("PLANE", &[Value(n)]) => Op::Plane(fit_nibble(n)?),
+        ("AUDIO", []) => Op::Audio,
+        ("MOVM", &[IndirectI, Range(0, v)]) => Op::RegDump(v),
+        ("MOVM", &[IndirectI, Range(v, v2)]) => Op::RegDumpRange { v, v2 },
+        ("MOVM", &[Range(0, v), IndirectI]) => Op::RegLoad(v),
+        ("MOVM", &[Range(v, v2), IndirectI]) => Op::RegLoadRange { v, v2 },
+        ("MOVM", &[Flags, Range(0, v)]) => Op::FlagsDump(v),
+        ("MOVM", &[Range(0, v), Flags]) => Op::FlagsLoad(v),
+        _ if Op::mnemonics().contains(&mnemonic) => {
+            return Err(format!("wrong operands for {mnemonic}"));
+        }
+        _ => return Err(format!("unknown instruction {mnemonic}")),
+    };
+    Ok(op)
+}
+
+fn fit_nibble(value: i64) -> Result<u8, String> {
+    u8::try_from(value)
+        .ok()
+        .filter(|&nibble| nibble < 0x10)
+        .ok_or(format!("{value} doesn't fit in 4 bits"))
+}
+
+// Negative bytes wrap around, so -1 is $ff.
+fn fit_byte(value: i64) -> Result<u8, String> {
+    if (-0x80..0x100).contains(&value) {
+        Ok(u8::try_from(value.rem_euclid(0x100)).unwrap())
+    } else {
+        Err(format!("{value} doesn't fit in a byte"))
+    }
+}
+
+fn fit_address(value: i64) -> Result<u16, String> {
+    u16::try_from(value)
+        .ok()
+        .filter(|&address| address < 0x1000)
+        .ok_or(format!("{} doesn't fit in 12 bits", hex(value)))
+}
+
+fn hex(value: i64) -> String {
+    if value < 0 {
+        format!("-${:x}", value.unsigned_abs())
+    } else {
+        format!("${value:x}")
+    }
+}
+
+fn fit_word(value: i64) -> Result<u16, String> {
+    if (-0x8000..0x10000).contains(&value) {
+        Ok(u16::try_from(value.rem_euclid(0x10000)).unwrap())
+    } else {
+        Err(format!("{value} doesn't fit in 16 bits"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Disassembly, Platform, State};
+
+    #[test]
+    fn assembles_labels_constants_and_data() {
+        let source = "\
+            COLUMNS equ 8
+            ; draws a sprite COLUMNS pixels in
+            start:  MVI  I, sprite
+                    MVI  V0,#COLUMNS
+                    mvi  v1, 0
+                    SPRITE V0, V1, #$3
+            loop:   JUMP loop
+            sprite: db %11111111, $81, sprite_end - sprite
+            sprite_end:
+                    dw $1234
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            vec![
+                0xa2, 0x0a, 0x60, 0x08, 0x61, 0x00, 0xd0, 0x13, 0x12, 0x08, //
+                0xff, 0x81, 0x03, 0x12, 0x34,
+            ],
+            rom
+        );
+
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        for _ in 0..4 {
+            state.emulate().unwrap();
+        }
+        assert_eq!(0x20a, state.i());
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(
+            "line 2: unknown instruction JUMPP",
+            error("CLS\nJUMPP $200")
+        );
+        assert_eq!("line 1: wrong operands for MOV", error("MOV V0, #1"));
+        assert_eq!(
+            "line 3: \"nowhere\" isn't defined",
+            error("\n\nJUMP nowhere")
+        );
+        assert_eq!("line 1: $1000 doesn't fit in 12 bits", error("CALL $1000"));
+        assert_eq!(
+            "line 2: \"a\" is defined more than once",
+            error("a: CLS\na: RTS")
+        );
+        assert!(error("A equ B\nB equ A\nMVI V0, A").contains("in terms of itself"));
+        assert_eq!(
+            "line 1: \"0x7fffffffffffffff + 1\" is too large",
+            error("MVI I, 0x7fffffffffffffff + 1")
+        );
+        assert_eq!(
+            "line 1: -$8000000000000000 doesn't fit in 12 bits",
+            error("MVI I, -0x7fffffffffffffff - 1")
+        );
+    }
+
+    #[test]
+    fn exported_disassembly_round_trips() {
+        let rom = vec![
+            0xa2, 0x10, // MVI      I, data_210
+            0x22, 0x0a, // CALL     sub_20a
+            0x50, 0x32, // MOVM     (I), V0-V3, but not the F355 it'd assemble to
+            0x01, 0xe0, // CLS, with a bit set
+            0x12, 0x06, // JUMP     loc_206
+            0xf0, 0x00, 0x12, 0x34, // MVI.L I, #$1234
+            0x00, 0xee, // RTS
+            0x3c, 0x42, 0x81, // (sprite)
+        ];
+        let disassembly = Disassembly::new(&rom, ROM_START, Platform::XoChip, &[ROM_START]);
+        assert_eq!(Ok(rom), assemble(&disassembly.export("test.ch8")));
+    }
+}
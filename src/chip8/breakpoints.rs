@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::Chip8Error;
+use super::state::State;
+
+// Everything the Debugger stops at.
+#[derive(Default)]
+pub struct Breakpoints {
+    // Addresses to stop at before running the instruction there, when the condition holds.
+    pub pc: BTreeMap<u16, Option<Condition>>,
+    // Addresses to stop at after an instruction writes to them.
+    pub watch: BTreeSet<u16>,
+    // Mnemonics to stop at before running any instruction that disassembles to them, e.g. SPRITE.
+    pub ops: BTreeSet<&'static str>,
+    // Stop before an instruction the platform can't run, instead of halting on it.
+    pub unknown_op: bool,
+}
+
+// Why the Debugger stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Break {
+    Breakpoint {
+        address: u16,
+    },
+    Watchpoint {
+        address: u16,
+        target: u16,
+    },
+    Op {
+        address: u16,
+        mnemonic: &'static str,
+    },
+    UnknownOp(Chip8Error),
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Break::Breakpoint { address } => write!(f, "breakpoint at ${address:03x}"),
+            Break::Watchpoint { address, target } => {
+                write!(f, "${address:03x} wrote to ${target:03x}")
+            }
+            Break::Op { address, mnemonic } => write!(f, "{mnemonic} at ${address:03x}"),
+            Break::UnknownOp(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Breakpoints {
+    pub fn toggle_pc(&mut self, address: u16) {
+        if self.pc.remove(&address).is_none() {
+            self.pc.insert(address, None);
+        }
+    }
+
+    // Checked before the instruction at pc runs.
+    #[must_use]
+    pub fn before(&self, chip8_state: &State) -> Option<Break> {
+        let address = chip8_state.pc;
+        if let Some(condition) = self.pc.get(&address)
+            && condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(chip8_state))
+        {
+            return Some(Break::Breakpoint { address });
+        }
+        match chip8_state.fetch() {
+            Ok(op) if self.ops.contains(op.instruction()) => Some(Break::Op {
+                address,
+                mnemonic: op.instruction(),
+            }),
+            Err(
+                error @ (Chip8Error::UnknownOpcode { .. } | Chip8Error::UnsupportedOpcode { .. }),
+            ) if self.unknown_op => Some(Break::UnknownOp(error)),
+            _ => None,
+        }
+    }
+
+    // Checked after an instruction ran, address being where it was.
+    #[must_use]
+    pub fn after(&self, chip8_state: &State, address: u16) -> Option<Break> {
+        chip8_state
+            .writes()
+            .iter()
+            .filter_map(|&target| u16::try_from(target).ok())
+            .find(|target| self.watch.contains(target))
+            .map(|target| Break::Watchpoint { address, target })
+    }
+}
+
+// Comparisons between registers and numbers joined by &&, e.g. `V3 == 0x10 && I >= $300`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String, // What was parsed, to show it again.
+    comparisons: Vec<(Operand, Comparison, Operand)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+    Number(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Condition {
+    #[must_use]
+    pub fn holds(&self, chip8_state: &State) -> bool {
+        self.comparisons.iter().all(|&(left, comparison, right)| {
+            let (left, right) = (left.value(chip8_state), right.value(chip8_state));
+            match comparison {
+                Comparison::Eq => left == right,
+                Comparison::Ne => left != right,
+                Comparison::Lt => left < right,
+                Comparison::Le => left <= right,
+                Comparison::Gt => left > right,
+                Comparison::Ge => left >= right,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let comparisons = source
+            .split("&&")
+            .map(parse_comparison)
+            .collect::<Result<_, _>>()?;
+        Ok(Condition {
+            source: source.trim().to_string(),
+            comparisons,
+        })
+    }
+}
+
+fn parse_comparison(source: &str) -> Result<(Operand, Comparison, Operand), String> {
+    // two character operators first, so <= isn't read as <
+    const COMPARISONS: [(&str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+    for (symbol, comparison) in COMPARISONS {
+        if let Some((left, right)) = source.split_once(symbol) {
+            return Ok((parse_operand(left)?, comparison, parse_operand(right)?));
+        }
+    }
+    Err(format!(
+        "expected a comparison like V3 == 0x10, got \"{}\"",
+        source.trim()
+    ))
+}
+
+fn parse_operand(source: &str) -> Result<Operand, String> {
+    let source = source.trim();
+    let operand = match source.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "PC" => Operand::Pc,
+        "SP" => Operand::Sp,
+        "DT" | "DELAY" => Operand::Delay,
+        "ST" | "SOUND" => Operand::Sound,
+        register if register.len() == 2 && register.starts_with('V') => {
+            let v = u8::from_str_radix(&register[1..], 16)
+                .map_err(|_| format!("unknown register \"{source}\""))?;
+            Operand::V(v)
+        }
+        _ => Operand::Number(parse_number(source)?),
+    };
+    Ok(operand)
+}
+
+// Hex with 0x or $ in front, otherwise decimal.
+fn parse_number(source: &str) -> Result<u16, String> {
+    let result = if let Some(hex) = source.strip_prefix("0x").or(source.strip_prefix('$')) {
+        u16::from_str_radix(hex, 16)
+    } else {
+        source.parse()
+    };
+    result.map_err(|_| format!("expected a register or number, got \"{source}\""))
+}
+
+impl Operand {
+    fn value(self, chip8_state: &State) -> u16 {
+        match self {
+            Operand::V(v) => u16::from(chip8_state.v[v as usize]),
+            Operand::I => chip8_state.i(),
+            Operand::Pc => chip8_state.pc,
+            Operand::Sp => u16::from(chip8_state.sp()),
+            Operand::Delay => u16::from(chip8_state.delay),
+            Operand::Sound => u16::from(chip8_state.sound()),
+            Operand::Number(number) => number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        let mut state = State::new();
+        state.v[0x3] = 0x10;
+        let condition: Condition = "V3 == 0x10 && pc >= $200".parse().unwrap();
+        assert!(condition.holds(&state));
+        state.v[0x3] = 0x11;
+        assert!(!condition.holds(&state));
+        assert!("v3 <= 17".parse::<Condition>().unwrap().holds(&state));
+
+        assert!("VG == 1".parse::<Condition>().is_err());
+        assert!("V3".parse::<Condition>().is_err());
+    }
+}
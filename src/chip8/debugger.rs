@@ -0,0 +1,294 @@
+use super::breakpoints::{Break, Breakpoints};
+use super::error::Chip8Error;
+use super::op::Op;
+use super::state::State;
+
+// Run control for a frontend: pausing, stepping and running until a point.
+// It drives the State an instruction at a time, so it can stop in the middle
+// of a frame and carry on from there without upsetting the timers.
+pub struct Debugger {
+    pub breakpoints: Breakpoints,
+    paused: bool,
+    stop: Option<Stop>,  // Where a step or run-to stops, while it's running.
+    frame_cycles: usize, // Instructions already run in the current frame.
+    hit: Option<Break>,  // The breakpoint that paused it, if one did.
+    // Set after stopping before an instruction, so it doesn't stop there again when resumed.
+    skip_breakpoints: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Instruction,
+    Frame,
+    // pc reaching the address, with no more than depth return addresses on the stack.
+    Reach { address: u16, depth: u8 },
+    // The stack dropping below depth, i.e. the current subroutine returning.
+    Return { depth: u8 },
+}
+
+impl Debugger {
+    #[must_use]
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Breakpoints::default(),
+            paused: false,
+            stop: None,
+            frame_cycles: 0,
+            hit: None,
+            skip_breakpoints: false,
+        }
+    }
+
+    // Whether nothing runs until the next step or resume.
+    #[must_use]
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    // Why it paused, when it was a breakpoint.
+    #[must_use]
+    pub fn hit(&self) -> Option<&Break> {
+        self.hit.as_ref()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.stop = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.stop = None;
+        self.hit = None;
+    }
+
+    // Starts the next frame from scratch, e.g. when the rom's been restarted in the middle of one.
+    pub fn reset_frame(&mut self) {
+        self.frame_cycles = 0;
+    }
+
+    pub fn step_instruction(&mut self) {
+        self.run_until(Stop::Instruction);
+    }
+
+    // Runs the rest of the current frame.
+    pub fn step_frame(&mut self) {
+        self.run_until(Stop::Frame);
+    }
+
+    // Like step_instruction, but a CALL runs until the subroutine returns.
+    pub fn step_over(&mut self, chip8_state: &State) {
+        let pc = chip8_state.pc;
+        match chip8_state.fetch() {
+            Ok(Op::Call(_)) => self.run_until(Stop::Reach {
+                address: pc.wrapping_add(2),
+                depth: chip8_state.sp(),
+            }),
+            _ => self.step_instruction(),
+        }
+    }
+
+    // Runs until the current subroutine returns, if there is one.
+    pub fn step_out(&mut self, chip8_state: &State) {
+        if chip8_state.sp() > 0 {
+            self.run_until(Stop::Return {
+                depth: chip8_state.sp(),
+            });
+        }
+    }
+
+    pub fn run_to(&mut self, address: u16) {
+        self.run_until(Stop::Reach {
+            address,
+            depth: u8::MAX,
+        });
+    }
+
+    fn run_until(&mut self, stop: Stop) {
+        self.resume();
+        self.stop = Some(stop);
+    }
+
+    fn break_at(&mut self, hit: Break) {
+        self.pause();
+        self.hit = Some(hit);
+    }
+
+    /// Stands in for `State::run_frame`, emulating up to the rest of the frame unless paused.
+    /// Breakpoints and the cpu halting also pause.
+    ///
+    /// # Errors
+    ///
+    /// Fails like `State::emulate`, pausing on the instruction that faulted.
+    pub fn run_frame(
+        &mut self,
+        chip8_state: &mut State,
+        cycles_per_frame: usize,
+    ) -> Result<(), Chip8Error> {
+        while !self.paused {
+            if self.frame_cycles >= cycles_per_frame {
+                chip8_state.end_frame();
+                self.frame_cycles = 0;
+                if self.stop == Some(Stop::Frame) {
+                    self.pause();
+                }
+                return Ok(());
+            }
+            if !chip8_state.rom_loaded {
+                self.frame_cycles = cycles_per_frame;
+                continue;
+            }
+            if chip8_state.halted().is_some() {
+                self.pause();
+                return Ok(());
+            }
+
+            if !std::mem::take(&mut self.skip_breakpoints)
+                && let Some(hit) = self.breakpoints.before(chip8_state)
+            {
+                self.break_at(hit);
+                self.skip_breakpoints = true;
+                return Ok(());
+            }
+
+            let address = chip8_state.pc;
+            let result = chip8_state.emulate();
+            self.frame_cycles += 1;
+            if result.is_err() {
+                self.pause();
+                return result;
+            }
+            if let Some(hit) = self.breakpoints.after(chip8_state, address) {
+                self.break_at(hit);
+            } else if self.reached_stop(chip8_state) {
+                self.pause();
+            }
+        }
+        Ok(())
+    }
+
+    // Checked after every instruction.
+    fn reached_stop(&self, chip8_state: &State) -> bool {
+        match self.stop {
+            None | Some(Stop::Frame) => false,
+            Some(Stop::Instruction) => true,
+            Some(Stop::Reach { address, depth }) => {
+                chip8_state.pc == address && chip8_state.sp() <= depth
+            }
+            Some(Stop::Return { depth }) => chip8_state.sp() < depth,
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_over_and_out() {
+        let rom = vec![
+            0x22, 0x06, // CALL $206
+            0x60, 0x01, // MVI  V0,#$01
+            0x12, 0x04, // JUMP $204
+            0x61, 0x02, // MVI  V1,#$02
+            0x00, 0xee, // RTS
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        debugger.run_frame(&mut state, 10).unwrap();
+        assert_eq!(0x200, state.pc);
+
+        debugger.step_over(&state);
+        debugger.run_frame(&mut state, 10).unwrap();
+        assert!(debugger.paused());
+        assert_eq!((0x202, 0x02), (state.pc, state.v[0x1]));
+
+        state.load_rom(&rom).unwrap();
+        debugger.step_instruction();
+        debugger.run_frame(&mut state, 10).unwrap();
+        assert_eq!(0x206, state.pc);
+        debugger.step_out(&state);
+        debugger.run_frame(&mut state, 10).unwrap();
+        assert_eq!((0x202, 0x00), (state.pc, state.sp()));
+
+        debugger.run_to(0x204);
+        debugger.run_frame(&mut state, 10).unwrap();
+        assert_eq!((0x204, 0x01), (state.pc, state.v[0x0]));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let rom = vec![
+            0x70, 0x01, // ADD    V0,#$01
+            0xa3, 0x00, // MVI    I,#$300
+            0xf0, 0x33, // MOVBCD V0
+            0xd0, 0x01, // SPRITE V0,V0,#$1
+            0x12, 0x00, // JUMP   $200
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger
+            .breakpoints
+            .pc
+            .insert(0x202, Some("V0 == 2".parse().unwrap()));
+        debugger.run_frame(&mut state, 100).unwrap();
+        assert_eq!(Some(&Break::Breakpoint { address: 0x202 }), debugger.hit());
+        assert_eq!((0x202, 0x02), (state.pc, state.v[0x0]));
+
+        debugger.breakpoints.pc.clear();
+        debugger.breakpoints.watch.insert(0x302);
+        debugger.resume();
+        debugger.run_frame(&mut state, 100).unwrap();
+        let hit = Break::Watchpoint {
+            address: 0x204,
+            target: 0x302,
+        };
+        assert_eq!(Some(&hit), debugger.hit());
+
+        debugger.breakpoints.ops.insert("SPRITE");
+        debugger.resume();
+        debugger.run_frame(&mut state, 100).unwrap();
+        let hit = Break::Op {
+            address: 0x206,
+            mnemonic: "SPRITE",
+        };
+        assert_eq!(Some(&hit), debugger.hit());
+
+        debugger.breakpoints.unknown_op = true;
+        state.memory[0x208] = 0xff;
+        debugger.resume();
+        debugger.run_frame(&mut state, 100).unwrap();
+        assert!(matches!(debugger.hit(), Some(Break::UnknownOp(_))));
+        assert_eq!((0x208, None), (state.pc, state.halted()));
+    }
+
+    #[test]
+    fn steps_keep_frame_timing() {
+        let rom = vec![
+            0x60, 0x05, // MVI  V0,#$05
+            0xf0, 0x15, // MOV  DELAY,V0
+            0x12, 0x04, // JUMP $204
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.step_instruction();
+        debugger.run_frame(&mut state, 3).unwrap();
+        debugger.step_instruction();
+        debugger.run_frame(&mut state, 3).unwrap();
+        assert_eq!(0x05, state.delay);
+        debugger.step_frame();
+        debugger.run_frame(&mut state, 3).unwrap();
+        assert_eq!((0x04, 0x204), (state.delay, state.pc));
+        assert!(debugger.paused());
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::assembler::assemble;
+use super::octo::octo_statement;
+use super::op::Op;
+use super::platform::Platform;
+
+// Tells code from data by following every path the program can take from its entry points,
+// instead of decoding every 2 bytes. Jump and call targets get labels, and so do addresses
+// loaded into I, which are usually sprites.
+pub struct Disassembly {
+    origin: u16, // Address of the first byte.
+    platform: Platform,
+    code: Vec<u8>,
+    bytes: Vec<Byte>, // What each byte of code is.
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Data,
+    Instruction, // The first byte of an instruction.
+    Operand,     // The rest of it.
+}
+
+// Ordered by precedence, an address that's both called and jumped to is a subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Data,
+    Jump,
+    Subroutine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Label {
+        address: u16,
+        name: String,
+    },
+    Instruction {
+        address: u16,
+        op: Op,
+        bytes: Vec<u8>,
+    },
+    Data {
+        address: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Line {
+    #[must_use]
+    pub fn address(&self) -> u16 {
+        match *self {
+            Line::Label { address, .. }
+            | Line::Instruction { address, .. }
+            | Line::Data { address, .. } => address,
+        }
+    }
+}
+
+impl Disassembly {
+    // Disassembles code loaded at origin, starting from each entry point. Code past the top of
+    // the 64 KiB address space is left out.
+    #[must_use]
+    pub fn new(code: &[u8], origin: u16, platform: Platform, entries: &[u16]) -> Self {
+        let code = &code[..code.len().min(0x1_0000 - usize::from(origin))];
+        let mut disassembly = Disassembly {
+            origin,
+            platform,
+            code: code.to_vec(),
+            bytes: vec![Byte::Data; code.len()],
+            labels: BTreeMap::new(),
+        };
+        let mut pending = entries.to_vec();
+        while let Some(address) = pending.pop() {
+            disassembly.trace(address, platform, &mut pending);
+        }
+        // a label in the middle of an instruction couldn't be put anywhere
+        let bytes = &disassembly.bytes;
+        disassembly.labels.retain(|&address, _| {
+            address
+                .checked_sub(origin)
+                .and_then(|offset| bytes.get(offset as usize))
+                .is_some_and(|&byte| byte != Byte::Operand)
+        });
+        disassembly
+    }
+
+    // Decodes the instruction at address, adding where it can go next to pending.
+    fn trace(&mut self, address: u16, platform: Platform, pending: &mut Vec<u16>) {
+        let Some(op) = self.decode(address, platform) else {
+            return;
+        };
+        let offset = (address - self.origin) as usize;
+        let size = op.size() as usize;
+        if self.bytes[offset..offset + size]
+            .iter()
+            .any(|&byte| byte != Byte::Data)
+        {
+            return; // already traced, or overlapping another instruction
+        }
+        self.bytes[offset] = Byte::Instruction;
+        self.bytes[offset + 1..offset + size].fill(Byte::Operand);
+
+        let next = address.wrapping_add(op.size());
+        match op {
+            Op::Jump(target) => {
+                self.add_label(target, LabelKind::Jump);
+                pending.push(target);
+            }
+            Op::Call(target) => {
+                self.add_label(target, LabelKind::Subroutine);
+                pending.extend([target, next]);
+            }
+            Op::Rts | Op::Exit => {}
+            // where it goes depends on V0, so it's only found when the rom gets there
+            Op::JumpPlusV0(target) => self.add_label(target, LabelKind::Jump),
+            Op::SkipEqLit { .. }
+            | Op::SkipNeLit { .. }
+            | Op::SkipEq { .. }
+            | Op::SkipNe { .. }
+            | Op::SkipKey(_)
+            | Op::SkipNoKey(_) => {
+                // skipping over an XO-CHIP long I load skips all 4 bytes of it
+                let skipped = match self.decode(next, platform) {
+                    Some(op) => op.size(),
+                    None => 2,
+                };
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            Op::SetI(target) | Op::LongSetI(target) => {
+                self.add_label(target, LabelKind::Data);
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    fn decode(&self, address: u16, platform: Platform) -> Option<Op> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        let op = Op::decode(self.code.get(offset..)?)?;
+        (op.platform() <= platform && offset + op.size() as usize <= self.code.len()).then_some(op)
+    }
+
+    fn add_label(&mut self, address: u16, kind: LabelKind) {
+        let offset = address.wrapping_sub(self.origin) as usize;
+        if address >= self.origin && offset < self.code.len() {
+            let label = self.labels.entry(address).or_insert(kind);
+            *label = kind.max(*label);
+        }
+    }
+
+    #[must_use]
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    // Where the byte at offset into the code is, None past the top of memory.
+    fn address(&self, offset: usize) -> Option<u16> {
+        u16::try_from(offset)
+            .ok()
+            .and_then(|offset| self.origin.checked_add(offset))
+    }
+
+    // Whether an instruction starts at the address.
+    #[must_use]
+    pub fn is_instruction(&self, address: u16) -> bool {
+        self.byte(address) == Some(Byte::Instruction)
+    }
+
+    // Whether the byte at the address is part of an instruction, so changing it changes the code.
+    #[must_use]
+    pub fn is_code(&self, address: u16) -> bool {
+        self.byte(address).is_some_and(|byte| byte != Byte::Data)
+    }
+
+    fn byte(&self, address: u16) -> Option<Byte> {
+        let offset = address.checked_sub(self.origin)?;
+        self.bytes.get(offset as usize).copied()
+    }
+
+    #[must_use]
+    pub fn label(&self, address: u16) -> Option<String> {
+        let kind = self.labels.get(&address)?;
+        let prefix = match kind {
+            LabelKind::Data => "data",
+            LabelKind::Jump => "loc",
+            LabelKind::Subroutine => "sub",
+        };
+        Some(format!("{prefix}_{address:03x}"))
+    }
+
+    // The listing, with up to data_per_line bytes of data on each line.
+    // Data is split at labels, so every label starts a line.
+    #[must_use]
+    pub fn lines(&self, data_per_line: usize) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let Some(address) = self.address(offset) else {
+                break;
+            };
+            if let Some(name) = self.label(address) {
+                lines.push(Line::Label { address, name });
+            }
+            if self.bytes[offset] == Byte::Instruction
+                && let Some(op) = Op::decode(&self.code[offset..])
+            {
+                let end = offset + op.size() as usize;
+                let bytes = self.code[offset..end].to_vec();
+                lines.push(Line::Instruction { address, op, bytes });
+                offset = end;
+            } else {
+                let mut end = offset + 1;
+                while end < self.code.len()
+                    && end - offset < data_per_line
+                    && self.bytes[end] == Byte::Data
+                    && self
+                        .address(end)
+                        .is_some_and(|address| !self.labels.contains_key(&address))
+                {
+                    end += 1;
+                }
+                let bytes = self.code[offset..end].to_vec();
+                lines.push(Line::Data { address, bytes });
+                offset = end;
+            }
+        }
+        lines
+    }
+
+    // Like Op::disassemble, with addresses that have labels replaced by them.
+    #[must_use]
+    pub fn format(&self, op: Op) -> (String, String) {
+        let (instruction, args) = op.disassemble();
+        let args = match op {
+            Op::Jump(address) | Op::Call(address) => self.label(address).unwrap_or(args),
+            Op::SetI(address) | Op::LongSetI(address) => match self.label(address) {
+                Some(label) => format!("I, {label}"),
+                None => args,
+            },
+            Op::JumpPlusV0(address) => match self.label(address) {
+                Some(label) => format!("{label}(V0)"),
+                None => args,
+            },
+            _ => args,
+        };
+        (instruction, args)
+    }
+
+    // The op the way Octo writes it, also with labels.
+    #[must_use]
+    pub fn format_octo(&self, op: Op) -> String {
+        octo_statement(op, |address| self.label(address))
+    }
+
+    // A source file that assembles back to the same bytes. Bytes that aren't code are kept
+    // as db, and so are instructions the assembler would encode differently.
+    #[must_use]
+    pub fn export(&self, name: &str) -> String {
+        let mut source = format!(
+            "; {name}\n; {} program, {} bytes at ${:03x}\n\n",
+            self.platform.name(),
+            self.code.len(),
+            self.origin
+        );
+        let mut sprite = false; // in data that's loaded into I, probably a sprite
+        for line in self.lines(8) {
+            match line {
+                Line::Label { address, name } => {
+                    sprite = self.labels.get(&address) == Some(&LabelKind::Data);
+                    if !source.ends_with("\n\n") {
+                        source.push('\n');
+                    }
+                    let _ = writeln!(source, "{name}:");
+                }
+                Line::Instruction { address, op, bytes } => {
+                    sprite = false;
+                    let (instruction, args) = self.format(op);
+                    let hex = hex_bytes(&bytes);
+                    // some instructions can't be told apart once they're written out, e.g.
+                    // MOVM (I), V0-V3 is both 5032 and f355
+                    let (plain_instruction, plain_args) = op.disassemble();
+                    let plain = format!("{plain_instruction}{plain_args}");
+                    if assemble(&plain).is_ok_and(|assembled| assembled == bytes) {
+                        push_line(&mut source, &format!("{instruction}{args}"), address, &hex);
+                    } else {
+                        let decoded = format!("{} {args}", instruction.trim());
+                        let comment = format!(
+                            "{hex}  {}, which assembles to other bytes",
+                            decoded.trim_end()
+                        );
+                        push_line(&mut source, &data_directive(&bytes), address, &comment);
+                    }
+                }
+                Line::Data { address, bytes } if sprite => {
+                    for (byte_address, &byte) in (address..).zip(&bytes) {
+                        let pixels: String = (0..8)
+                            .map(|column| if byte << column & 0x80 != 0 { '#' } else { '.' })
+                            .collect();
+                        push_line(&mut source, &data_directive(&[byte]), byte_address, &pixels);
+                    }
+                }
+                Line::Data { address, bytes } => {
+                    push_line(&mut source, &data_directive(&bytes), address, "");
+                }
+            }
+        }
+        source
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn data_directive(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("${byte:02x}")).collect();
+    format!("{:-10} {}", "db", bytes.join(", "))
+}
+
+// An indented statement with its address and a comment lined up after it.
+fn push_line(source: &mut String, statement: &str, address: u16, comment: &str) {
+    let line = format!("    {statement:-40} ; ${address:03x}  {comment}");
+    source.push_str(line.trim_end());
+    source.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_control_flow() {
+        let rom = vec![
+            0xa2, 0x0c, // MVI      I,data_20c
+            0x22, 0x08, // CALL     sub_208
+            0x12, 0x0f, // JUMP     loc_20f
+            0x00, 0x00, // (data)
+            0x3f, 0x00, // SKIP.EQ  VF,#$00
+            0x00, 0xee, // RTS
+            0xff, 0x81, 0x00, // (data)
+            0x00, 0xfd, // EXIT, at an odd address
+        ];
+        let disassembly = Disassembly::new(&rom, 0x200, Platform::SuperChip, &[0x200]);
+        let lines: Vec<_> = disassembly
+            .lines(8)
+            .iter()
+            .map(|line| match line {
+                Line::Label { name, .. } => name.clone(),
+                Line::Instruction { op, .. } => op.instruction().to_string(),
+                Line::Data { bytes, .. } => format!("{bytes:02x?}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "MVI",
+                "CALL",
+                "JUMP",
+                "[00, 00]",
+                "sub_208",
+                "SKIP.EQ",
+                "RTS",
+                "data_20c",
+                "[ff, 81, 00]",
+                "loc_20f",
+                "EXIT",
+            ],
+            lines
+        );
+        assert!(disassembly.is_instruction(0x20f));
+        assert!(disassembly.is_code(0x209) && !disassembly.is_code(0x206));
+        assert_eq!("I, data_20c", disassembly.format(Op::SetI(0x20c)).1);
+        assert_eq!("sub_208", disassembly.format_octo(Op::Call(0x208)));
+    }
+
+    #[test]
+    fn stops_at_top_of_memory() {
+        let rom: Vec<u8> = [0x00, 0xe0].repeat(0x8000); // CLS all the way
+        let disassembly = Disassembly::new(&rom, 0x200, Platform::XoChip, &[0x200]);
+        let lines = disassembly.lines(8);
+        assert_eq!(0x8000 - 0x100, lines.len());
+        assert_eq!(Some(0xfffe), lines.last().map(Line::address));
+    }
+
+    #[test]
+    fn export() {
+        let rom = vec![
+            0xa2, 0x06, // MVI      I,data_206
+            0x01, 0xe0, // CLS, with a bit set that the decoder ignores
+            0x12, 0x02, // JUMP     loc_202
+            0xc3, // (sprite)
+        ];
+        let source = Disassembly::new(&rom, 0x200, Platform::Chip8, &[0x200]).export("test.ch8");
+        let lines: Vec<_> = source.lines().collect();
+        assert_eq!(
+            vec![
+                "; test.ch8",
+                "; CHIP-8 program, 7 bytes at $200",
+                "",
+                "    MVI        I, data_206                   ; $200  a206",
+                "",
+                "loc_202:",
+                "    db         $01, $e0                      ; $202  01e0  CLS, which assembles to other bytes",
+                "    JUMP       loc_202                       ; $204  1202",
+                "",
+                "data_206:",
+                "    db         $c3                           ; $206  ##....##",
+            ],
+            lines
+        );
+    }
+}
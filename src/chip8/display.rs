@@ -0,0 +1,200 @@
+use super::savestate::{Reader, SaveStateError, Writer};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// The screen, kept apart from memory so roms can use all of it.
+pub struct Display {
+    hires: bool,
+    // One row after the other, at the current resolution.
+    // Each pixel has a bit per plane, plane 1 being the lowest bit.
+    pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    plane_count: u8,
+    planes: u8,  // Planes that get drawn to, cleared and scrolled.
+    dirty: bool, // Set whenever a pixel or the resolution changes.
+}
+
+impl Display {
+    #[must_use]
+    pub fn new() -> Self {
+        Display {
+            hires: false,
+            pixels: [0x00; HIRES_WIDTH * HIRES_HEIGHT],
+            plane_count: 1,
+            planes: 0b01,
+            dirty: true,
+        }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { WIDTH }
+    }
+
+    #[must_use]
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { HEIGHT }
+    }
+
+    // Switching resolution clears the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels.fill(0x00);
+        self.dirty = true;
+    }
+
+    // Planes the display doesn't have are dropped from the selection.
+    pub fn set_plane_count(&mut self, plane_count: u8) {
+        self.plane_count = plane_count;
+        self.set_planes(self.planes);
+    }
+
+    #[must_use]
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << self.plane_count) - 1);
+    }
+
+    // Bits of the planes the pixel is on in.
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
+
+    // Flips the pixel on one plane, wrapping around the edges.
+    // Returns whether it got turned off.
+    pub fn xor_pixel(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        // bounds check
+        let x = x % self.width();
+        let y = y % self.height();
+
+        let pixel = &mut self.pixels[y * self.width() + x];
+        let flipped = *pixel & plane != 0;
+        *pixel ^= plane;
+        self.dirty = true;
+
+        flipped
+    }
+
+    pub fn clear(&mut self) {
+        for pixel in &mut self.pixels {
+            *pixel &= !self.planes;
+        }
+        self.dirty = true;
+    }
+
+    // Moves the selected planes by dx, dy pixels, filling in with blank pixels.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width(), self.height());
+        let old_pixels = self.pixels;
+        for y in 0..height {
+            for x in 0..width {
+                let from_x = x.checked_add_signed(-dx).filter(|&x| x < width);
+                let from_y = y.checked_add_signed(-dy).filter(|&y| y < height);
+                let moved = match (from_x, from_y) {
+                    (Some(from_x), Some(from_y)) => {
+                        old_pixels[from_y * width + from_x] & self.planes
+                    }
+                    _ => 0x00,
+                };
+                let pixel = &mut self.pixels[y * width + x];
+                *pixel = (*pixel & !self.planes) | moved;
+            }
+        }
+        self.dirty = true;
+    }
+
+    // Returns whether the display changed since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // width() * height() pixels, one byte per pixel with a bit set for each plane it's on in
+    #[must_use]
+    pub fn frame(&self) -> Vec<u8> {
+        (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| self.pixel(x, y)))
+            .collect()
+    }
+
+    pub(super) fn save(&self, writer: &mut Writer) {
+        writer.bool(self.hires);
+        writer.u8(self.plane_count);
+        writer.u8(self.planes);
+        writer.bytes(&self.pixels);
+    }
+
+    pub(super) fn load(reader: &mut Reader) -> Result<Self, SaveStateError> {
+        let hires = reader.bool()?;
+        let plane_count = reader.u8()?;
+        if !(1..=2).contains(&plane_count) {
+            return Err(SaveStateError::Invalid("plane count"));
+        }
+        // only planes the display has can be selected or lit
+        let all_planes = (1 << plane_count) - 1;
+        let planes = reader.u8()?;
+        if planes & !all_planes != 0 {
+            return Err(SaveStateError::Invalid("plane selection"));
+        }
+        let pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT] = reader.array()?;
+        if pixels.iter().any(|&pixel| pixel & !all_planes != 0) {
+            return Err(SaveStateError::Invalid("pixel"));
+        }
+        Ok(Display {
+            hires,
+            pixels,
+            plane_count,
+            planes,
+            dirty: true,
+        })
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_only_selected_planes() {
+        let mut display = Display::new();
+        display.set_plane_count(2);
+        display.xor_pixel(0, 0, 0b01);
+        display.xor_pixel(0, 0, 0b10);
+        display.set_planes(0b10);
+        display.scroll(1, 1);
+        assert_eq!(0b01, display.pixel(0, 0));
+        assert_eq!(0b10, display.pixel(1, 1));
+    }
+
+    #[test]
+    fn load_rejects_invalid_planes() {
+        let mut writer = Writer::new();
+        Display::new().save(&mut writer);
+        let saved = writer.finish();
+        let load = |data: &[u8]| {
+            let mut reader = Reader::new(data)?;
+            Display::load(&mut reader).map(|_| ())
+        };
+        assert_eq!(Ok(()), load(&saved));
+        let mut corrupt = saved.clone();
+        corrupt[12] = 0b10; // plane 2, on a one plane display
+        assert_eq!(
+            Err(SaveStateError::Invalid("plane selection")),
+            load(&corrupt)
+        );
+        let mut corrupt = saved;
+        corrupt[13] = 0xff;
+        assert_eq!(Err(SaveStateError::Invalid("pixel")), load(&corrupt));
+    }
+}
@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fmt;
+
+use super::platform::Platform;
+
+// Everything that can stop a rom, addresses are where the instruction was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode {
+        address: u16,
+        opcode: u16,
+    },
+    UnsupportedOpcode {
+        address: u16,
+        opcode: u16,
+        platform: Platform,
+    },
+    StackOverflow {
+        address: u16,
+    },
+    StackUnderflow {
+        address: u16,
+    },
+    MemoryOutOfBounds {
+        address: u16,
+        target: usize,
+    },
+    RomTooLarge {
+        size: usize,
+        capacity: usize,
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {opcode:04x} at ${address:03x}")
+            }
+            Chip8Error::UnsupportedOpcode {
+                address,
+                opcode,
+                platform,
+            } => write!(
+                f,
+                "opcode {opcode:04x} at ${address:03x} isn't supported on {}",
+                platform.name()
+            ),
+            Chip8Error::StackOverflow { address } => {
+                write!(f, "stack overflow at ${address:03x}")
+            }
+            Chip8Error::StackUnderflow { address } => {
+                write!(f, "stack underflow at ${address:03x}")
+            }
+            Chip8Error::MemoryOutOfBounds { address, target } => {
+                write!(
+                    f,
+                    "memory access out of bounds at ${address:03x}: ${target:03x}"
+                )
+            }
+            Chip8Error::RomTooLarge { size, capacity } => {
+                write!(f, "rom is {size} bytes, only {capacity} fit in memory")
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+// Why the cpu stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Halt {
+    Exited, // The rom ran SUPER-CHIP's exit instruction.
+    Fault(Chip8Error),
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Halt::Exited => write!(f, "exited"),
+            Halt::Fault(error) => write!(f, "{error}"),
+        }
+    }
+}
@@ -1,7 +1,43 @@
 mod op;
 pub use op::Op;
 
+mod assembler;
+pub use assembler::{AssemblyError, assemble};
+
+mod breakpoints;
+pub use breakpoints::{Break, Breakpoints, Condition};
+
+mod debugger;
+pub use debugger::Debugger;
+
+mod disassembly;
+pub use disassembly::{Disassembly, LabelKind, Line};
+
+mod display;
+pub use display::{Display, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+mod error;
+pub use error::{Chip8Error, Halt};
+
 mod font;
 
+mod movie;
+pub use movie::{Movie, MovieError};
+
+mod octo;
+pub use octo::compile_octo;
+
+mod platform;
+pub use platform::Platform;
+
+mod quirks;
+pub use quirks::{LoadStore, Preset, Quirks};
+
+mod rewind;
+pub use rewind::Rewind;
+
+mod savestate;
+pub use savestate::SaveStateError;
+
 mod state;
-pub use state::State;
+pub use state::{ROM_START, State, rom_hash};
@@ -0,0 +1,235 @@
+use std::error::Error;
+use std::fmt;
+
+use super::error::Chip8Error;
+use super::platform::Platform;
+use super::quirks::Quirks;
+use super::savestate::{Reader, SaveStateError, Writer};
+use super::state::{State, rom_hash};
+
+// Movies are laid out like save states, with their own magic bytes and version.
+pub const MAGIC: [u8; 8] = *b"CH8UWUMV";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    NotAMovie,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+    WrongRom, // The movie was recorded playing another rom.
+    Rom(Chip8Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {version} isn't supported, only version {VERSION} is"
+            ),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {field}"),
+            MovieError::WrongRom => write!(f, "movie was recorded with another rom"),
+            MovieError::Rom(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        match error {
+            SaveStateError::NotASaveState => MovieError::NotAMovie,
+            SaveStateError::UnsupportedVersion(version) => MovieError::UnsupportedVersion(version),
+            SaveStateError::Truncated => MovieError::Truncated,
+            SaveStateError::Invalid(field) => MovieError::Invalid(field),
+        }
+    }
+}
+
+impl From<Chip8Error> for MovieError {
+    fn from(error: Chip8Error) -> Self {
+        MovieError::Rom(error)
+    }
+}
+
+// The keys held on every frame since a rom was loaded, and everything else the rom's run
+// depends on, so playing it back runs the rom exactly the same way again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub rpl: [u8; 16], // SUPER-CHIP's flag registers, which survive loading the rom.
+    pub cycles_per_frame: usize,
+    pub frames: Vec<[bool; 16]>,
+}
+
+impl Movie {
+    /// Reloads the rom, so the recording starts from its first frame.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the rom doesn't fit in memory.
+    pub fn record(
+        chip8_state: &mut State,
+        rom: &[u8],
+        cycles_per_frame: usize,
+    ) -> Result<Self, Chip8Error> {
+        chip8_state.load_rom(rom)?;
+        Ok(Movie {
+            rom_hash: chip8_state.rom_hash(),
+            seed: chip8_state.seed(),
+            platform: chip8_state.platform(),
+            quirks: chip8_state.quirks,
+            rpl: chip8_state.rpl(),
+            cycles_per_frame,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Loads the rom the way it was when the movie was recorded, ready to play frame 0.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `WrongRom` for another rom than the movie was recorded with.
+    pub fn start(&self, chip8_state: &mut State, rom: &[u8]) -> Result<(), MovieError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(MovieError::WrongRom);
+        }
+        chip8_state.set_platform(self.platform);
+        chip8_state.quirks = self.quirks;
+        chip8_state.load_rom(rom)?;
+        chip8_state.set_rpl(self.rpl);
+        chip8_state.reseed(self.seed);
+        Ok(())
+    }
+
+    // Records the keys held for the frame that's about to run. Anything recorded after it is
+    // dropped, so rewinding or loading a state records over what happened since.
+    pub fn record_frame(&mut self, chip8_state: &State) {
+        self.frames.truncate(frame_index(chip8_state));
+        self.frames.push(chip8_state.keyboard);
+    }
+
+    // Holds the keys recorded for the frame that's about to run. False once the movie's over.
+    pub fn play_frame(&self, chip8_state: &mut State) -> bool {
+        match self.frames.get(frame_index(chip8_state)) {
+            Some(&keyboard) => {
+                chip8_state.keyboard = keyboard;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Each frame's keys are a 16-bit mask, key 0 in the lowest bit.
+    #[must_use]
+    pub fn save(&self) -> Vec<u8> {
+        let mut writer = Writer::with_header(MAGIC, VERSION);
+        writer.u64(self.rom_hash);
+        writer.u64(self.seed);
+        writer.platform(self.platform);
+        writer.quirks(self.quirks);
+        writer.bytes(&self.rpl);
+        writer.u64(self.cycles_per_frame as u64);
+        writer.u64(self.frames.len() as u64);
+        for keyboard in &self.frames {
+            let mask = (0..16)
+                .filter(|&key| keyboard[key])
+                .fold(0, |mask, key| mask | 1 << key);
+            writer.u16(mask);
+        }
+        writer.finish()
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the data isn't a movie from this version, or is truncated or corrupt.
+    pub fn load(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = Reader::with_header(data, MAGIC, VERSION)?;
+        let rom_hash = reader.u64()?;
+        let seed = reader.u64()?;
+        let platform = reader.platform()?;
+        let quirks = reader.quirks()?;
+        let rpl = reader.array()?;
+        let cycles_per_frame = reader.usize()?;
+        let frame_count = reader.usize()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let mask = reader.u16()?;
+            frames.push(std::array::from_fn(|key| mask & 1 << key != 0));
+        }
+        reader.finish()?;
+        Ok(Movie {
+            rom_hash,
+            seed,
+            platform,
+            quirks,
+            rpl,
+            cycles_per_frame,
+            frames,
+        })
+    }
+}
+
+fn frame_index(chip8_state: &State) -> usize {
+    usize::try_from(chip8_state.frame()).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movie_replays_run() {
+        // loop: V0 = random byte, V1 += V0, V2 += 1 while key 5 is held
+        let rom = [
+            0xc0, 0xff, 0x81, 0x04, 0x63, 0x05, 0xe3, 0x9e, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00,
+        ];
+        let mut state = State::new();
+        state.set_platform(Platform::SuperChip);
+        state.set_rpl([0x07; 16]);
+        let mut movie = Movie::record(&mut state, &rom, 7).unwrap();
+        for frame in 0..120 {
+            state.keyboard[0x5] = frame % 30 < 10;
+            movie.record_frame(&state);
+            state.run_frame(movie.cycles_per_frame).unwrap();
+        }
+        // going back to frame 120 drops the 20 frames recorded since
+        let rewound = state.save_state();
+        for _ in 0..20 {
+            movie.record_frame(&state);
+            state.run_frame(movie.cycles_per_frame).unwrap();
+        }
+        state.load_state(&rewound).unwrap();
+        assert_eq!(140, movie.frames.len());
+        movie.record_frame(&state);
+        assert_eq!(121, movie.frames.len());
+
+        let movie = Movie::load(&movie.save()).unwrap();
+        let mut replay = State::new();
+        movie.start(&mut replay, &rom).unwrap();
+        assert_eq!(Platform::SuperChip, replay.platform());
+        assert_eq!([0x07; 16], replay.rpl());
+        while movie.play_frame(&mut replay) {
+            replay.run_frame(movie.cycles_per_frame).unwrap();
+        }
+        state.run_frame(movie.cycles_per_frame).unwrap();
+        assert_eq!(state.save_state(), replay.save_state());
+        assert_ne!(0, replay.v[0x2]);
+
+        assert_eq!(
+            Err(MovieError::WrongRom),
+            movie.start(&mut replay, &rom[2..])
+        );
+        assert_eq!(
+            Err(MovieError::NotAMovie),
+            Movie::load(&state.save_state()).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,980 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::assembler::AssemblyError;
+use super::op::Op;
+use super::state::ROM_START;
+
+/// Compiles Octo source (<https://github.com/JohnEarnest/Octo>) into a rom that
+/// `State::load_rom` can load. Supports labels, :alias, :const, :calc, :macro, :byte, :org,
+/// :call, loop/while/again, and if ... then / if ... begin ... else ... end.
+/// Execution starts at the label main.
+///
+/// # Errors
+///
+/// Fails on the first statement that doesn't compile, with its line number.
+pub fn compile_octo(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut compiler = Compiler::new(source);
+    while compiler.tokens.front().is_some() {
+        compiler.statement().map_err(|message| AssemblyError {
+            line: compiler.line,
+            message,
+        })?;
+    }
+    compiler.finish().map_err(|message| AssemblyError {
+        line: compiler.line,
+        message,
+    })
+}
+
+// An op the way Octo writes it, with label(address) naming addresses that have labels.
+pub(super) fn octo_statement(op: Op, label: impl Fn(u16) -> Option<String>) -> String {
+    let address = |address: u16| label(address).unwrap_or(format!("0x{address:03x}"));
+    match op {
+        Op::Cls => String::from("clear"),
+        Op::Rts => String::from("return"),
+        Op::ScrollDown(n) => format!("scroll-down {n}"),
+        Op::ScrollUp(n) => format!("scroll-up {n}"),
+        Op::ScrollRight => String::from("scroll-right"),
+        Op::ScrollLeft => String::from("scroll-left"),
+        Op::Exit => String::from("exit"),
+        Op::Lores => String::from("lores"),
+        Op::Hires => String::from("hires"),
+        Op::Jump(target) => format!("jump {}", address(target)),
+        Op::JumpPlusV0(target) => format!("jump0 {}", address(target)),
+        // calling a label is just its name
+        Op::Call(target) => label(target).unwrap_or(format!(":call 0x{target:03x}")),
+        // skips run the next statement when they don't skip, so they read as the opposite
+        Op::SkipEqLit { v, lit } => format!("if v{v:x} != 0x{lit:02x} then"),
+        Op::SkipNeLit { v, lit } => format!("if v{v:x} == 0x{lit:02x} then"),
+        Op::SkipEq { v, v2 } => format!("if v{v:x} != v{v2:x} then"),
+        Op::SkipNe { v, v2 } => format!("if v{v:x} == v{v2:x} then"),
+        Op::SkipKey(v) => format!("if v{v:x} -key then"),
+        Op::SkipNoKey(v) => format!("if v{v:x} key then"),
+        Op::RegDumpRange { v, v2 } => format!("save v{v:x} - v{v2:x}"),
+        Op::RegLoadRange { v, v2 } => format!("load v{v:x} - v{v2:x}"),
+        Op::MviLit { v, lit } => format!("v{v:x} := 0x{lit:02x}"),
+        Op::AdiLit { v, lit } => format!("v{v:x} += 0x{lit:02x}"),
+        Op::Mov { v, v2 } => format!("v{v:x} := v{v2:x}"),
+        Op::Or { v, v2 } => format!("v{v:x} |= v{v2:x}"),
+        Op::And { v, v2 } => format!("v{v:x} &= v{v2:x}"),
+        Op::Xor { v, v2 } => format!("v{v:x} ^= v{v2:x}"),
+        Op::Add { v, v2 } => format!("v{v:x} += v{v2:x}"),
+        Op::Sub { v, v2 } => format!("v{v:x} -= v{v2:x}"),
+        Op::Shr { v, v2 } => format!("v{v:x} >>= v{v2:x}"),
+        Op::Subb { v, v2 } => format!("v{v:x} =- v{v2:x}"),
+        Op::Shl { v, v2 } => format!("v{v:x} <<= v{v2:x}"),
+        Op::SetI(target) => format!("i := {}", address(target)),
+        Op::LongSetI(target) => match label(target) {
+            Some(label) => format!("i := long {label}"),
+            None => format!("i := long 0x{target:04x}"),
+        },
+        Op::Rand { v, lit } => format!("v{v:x} := random 0x{lit:02x}"),
+        Op::Draw { v, v2, lit } => format!("sprite v{v:x} v{v2:x} {lit}"),
+        Op::GetDelay(v) => format!("v{v:x} := delay"),
+        Op::GetKey(v) => format!("v{v:x} := key"),
+        Op::Delay(v) => format!("delay := v{v:x}"),
+        Op::Sound(v) => format!("buzzer := v{v:x}"),
+        Op::AddI(v) => format!("i += v{v:x}"),
+        Op::SpriteChar(v) => format!("i := hex v{v:x}"),
+        Op::BigSpriteChar(v) => format!("i := bighex v{v:x}"),
+        Op::MovBcd(v) => format!("bcd v{v:x}"),
+        Op::RegDump(v) => format!("save v{v:x}"),
+        Op::RegLoad(v) => format!("load v{v:x}"),
+        Op::FlagsDump(v) => format!("saveflags v{v:x}"),
+        Op::FlagsLoad(v) => format!("loadflags v{v:x}"),
+        Op::Plane(n) => format!("plane {n}"),
+        Op::Audio => String::from("audio"),
+        Op::Pitch(v) => format!("pitch := v{v:x}"),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+}
+
+// An address a label is written to once it's defined.
+struct Fixup<'a> {
+    address: u16, // Of the instruction.
+    name: &'a str,
+    line: usize,
+    long: bool, // Whether it's i := long, with a 16 bit address after the opcode.
+}
+
+// Open loop/again and if/begin/end blocks.
+enum Block {
+    Loop { start: u16, whiles: Vec<u16> }, // whiles jump past again.
+    If { jump: u16 },                      // Jumps to else, or end if there isn't one.
+    Else { jump: u16 },                    // Jumps from the end of the if part to end.
+}
+
+// Right hand sides of comparisons.
+#[derive(Clone, Copy)]
+enum Operand {
+    V(u8),
+    Value(u8),
+}
+
+#[derive(Clone, Copy)]
+enum Condition {
+    Eq(u8, Operand),
+    Ne(u8, Operand),
+    Key(u8),
+    NoKey(u8),
+    Less(u8, Operand),
+    Greater(u8, Operand),
+    LessOrEqual(u8, Operand),
+    GreaterOrEqual(u8, Operand),
+}
+
+struct Compiler<'a> {
+    tokens: VecDeque<Token<'a>>,
+    line: usize, // Of the last token taken, for errors.
+    rom: Vec<u8>,
+    pc: u16, // Where the next byte goes.
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, i64>,
+    aliases: HashMap<&'a str, u8>,
+    macros: HashMap<&'a str, Macro<'a>>,
+    expansions: usize, // Macros expanded so far, to stop ones that expand forever.
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block>,
+}
+
+// Words statements start with, which can't be the names of labels and constants.
+const KEYWORDS: [&str; 34] = [
+    ":",
+    ":alias",
+    ":const",
+    ":calc",
+    ":macro",
+    ":byte",
+    ":org",
+    ":call",
+    "loop",
+    "again",
+    "while",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "clear",
+    "return",
+    "exit",
+    "lores",
+    "hires",
+    "jump",
+    "jump0",
+    "sprite",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "delay",
+    "buzzer",
+    "i",
+];
+
+impl<'a> Compiler<'a> {
+    fn new(source: &'a str) -> Self {
+        let tokens = (1..)
+            .zip(source.lines())
+            .flat_map(|(line, text)| {
+                text.split_whitespace()
+                    .take_while(|text| !text.starts_with('#'))
+                    .map(move |text| Token { text, line })
+            })
+            .collect();
+        let mut compiler = Compiler {
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            pc: ROM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        };
+        // the rom starts with a jump to main, which goes away if main comes right after it
+        compiler.fixups.push(Fixup {
+            address: ROM_START,
+            name: "main",
+            line: 1,
+            long: false,
+        });
+        let _ = compiler.emit(Op::Jump(0)); // can't fail with nothing written yet
+        compiler
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, String> {
+        if let Some(block) = self.blocks.last() {
+            return Err(String::from(match block {
+                Block::Loop { .. } => "loop without again",
+                Block::If { .. } | Block::Else { .. } => "begin without end",
+            }));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let Some(&target) = self.labels.get(fixup.name) else {
+                return Err(format!("\"{}\" isn't defined", fixup.name));
+            };
+            let offset = usize::from(fixup.address - ROM_START);
+            if fixup.long {
+                self.rom[offset + 2..offset + 4].copy_from_slice(&target.to_be_bytes());
+            } else {
+                let target = fit_address(i64::from(target))?.to_be_bytes();
+                self.rom[offset] = self.rom[offset] & 0xf0 | target[0];
+                self.rom[offset + 1] = target[1];
+            }
+        }
+        Ok(std::mem::take(&mut self.rom))
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| String::from("unexpected end of file"))?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.front().map(|token| token.text)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected {expected}, got \"{token}\"")),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let offset = usize::from(self.pc - ROM_START);
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.pc = u16::try_from(usize::from(self.pc) + bytes.len())
+            .map_err(|_| String::from("the program doesn't fit in memory"))?;
+        Ok(())
+    }
+
+    fn emit(&mut self, op: Op) -> Result<(), String> {
+        self.write(&op.encode())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some(v) = self.register(token) {
+            return self.assignment(v);
+        }
+        match token {
+            ":" => self.label(),
+            ":breakpoint" => self.next().map(|_| ()), // the debugger has its own breakpoints
+            ":monitor" => self.next().and_then(|_| self.next()).map(|_| ()),
+            _ if token.starts_with(':') => self.directive(token),
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.pc,
+                    whiles: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => self.while_(),
+            "again" => self.again(),
+            "if" => self.if_(),
+            "else" => self.else_(),
+            "end" => self.end(),
+            "clear" => self.emit(Op::Cls),
+            "return" | ";" => self.emit(Op::Rts),
+            "exit" => self.emit(Op::Exit),
+            "lores" => self.emit(Op::Lores),
+            "hires" => self.emit(Op::Hires),
+            "scroll-down" => {
+                let n = fit_nibble(self.value()?)?;
+                self.emit(Op::ScrollDown(n))
+            }
+            "scroll-up" => {
+                let n = fit_nibble(self.value()?)?;
+                self.emit(Op::ScrollUp(n))
+            }
+            "scroll-right" => self.emit(Op::ScrollRight),
+            "scroll-left" => self.emit(Op::ScrollLeft),
+            "jump" => {
+                let address = self.address(false)?;
+                self.emit(Op::Jump(address))
+            }
+            "jump0" => {
+                let address = self.address(false)?;
+                self.emit(Op::JumpPlusV0(address))
+            }
+            "sprite" => {
+                let v = self.expect_register()?;
+                let v2 = self.expect_register()?;
+                let lit = fit_nibble(self.value()?)?;
+                self.emit(Op::Draw { v, v2, lit })
+            }
+            "bcd" => {
+                let v = self.expect_register()?;
+                self.emit(Op::MovBcd(v))
+            }
+            "save" | "load" => self.save_load(token),
+            "saveflags" => {
+                let v = self.expect_register()?;
+                self.emit(Op::FlagsDump(v))
+            }
+            "loadflags" => {
+                let v = self.expect_register()?;
+                self.emit(Op::FlagsLoad(v))
+            }
+            "plane" => {
+                let n = fit_nibble(self.value()?)?;
+                self.emit(Op::Plane(n))
+            }
+            "audio" => self.emit(Op::Audio),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let v = self.expect_register()?;
+                self.emit(match token {
+                    "delay" => Op::Delay(v),
+                    "buzzer" => Op::Sound(v),
+                    _ => Op::Pitch(v),
+                })
+            }
+            "i" => self.i_assignment(),
+            _ if self.macros.contains_key(token) => self.expand(token),
+            _ => match parse_number(token) {
+                Some(value) => self.write(&[fit_byte(value)?]),
+                // anything else is a subroutine, which is called by naming it
+                None if is_name(token) => {
+                    let address = self.address_of(token, false);
+                    self.emit(Op::Call(address))
+                }
+                None => Err(format!("unexpected \"{token}\"")),
+            },
+        }
+    }
+
+    // save vx, load vx, and the XO-CHIP ranges save vx - vy and load vx - vy.
+    fn save_load(&mut self, token: &str) -> Result<(), String> {
+        let v = self.expect_register()?;
+        let op = if self.peek() == Some("-") {
+            self.next()?;
+            let v2 = self.expect_register()?;
+            if token == "save" {
+                Op::RegDumpRange { v, v2 }
+            } else {
+                Op::RegLoadRange { v, v2 }
+            }
+        } else if token == "save" {
+            Op::RegDump(v)
+        } else {
+            Op::RegLoad(v)
+        };
+        self.emit(op)
+    }
+
+    fn directive(&mut self, token: &str) -> Result<(), String> {
+        match token {
+            ":alias" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let v = self
+                    .register(token)
+                    .ok_or(format!("expected a register, got \"{token}\""))?;
+                self.aliases.insert(name, v);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()?
+                } else {
+                    self.value()?
+                };
+                self.write(&[fit_byte(value)?])
+            }
+            ":org" => {
+                let address = self.value()?;
+                self.pc = u16::try_from(address)
+                    .ok()
+                    .filter(|&address| address >= ROM_START)
+                    .ok_or(format!(":org {address} is outside the program"))?;
+                Ok(())
+            }
+            ":call" => {
+                let address = self.address(false)?;
+                self.emit(Op::Call(address))
+            }
+            _ => Err(format!("{token} isn't supported")),
+        }
+    }
+
+    fn label(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        if name == "main" && self.pc == ROM_START + 2 && self.rom.len() == 2 {
+            // nothing between the jump to main and main, so the jump isn't needed
+            self.rom.clear();
+            self.pc = ROM_START;
+            self.fixups.retain(|fixup| fixup.address != ROM_START);
+        }
+        if self.labels.insert(name, self.pc).is_some() {
+            return Err(format!("\"{name}\" is defined more than once"));
+        }
+        Ok(())
+    }
+
+    // A new name for a label, constant, alias or macro.
+    fn name(&mut self) -> Result<&'a str, String> {
+        let name = self.next()?;
+        if !is_name(name) || KEYWORDS.contains(&name) || self.register(name).is_some() {
+            return Err(format!("\"{name}\" can't be used as a name"));
+        }
+        Ok(name)
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(&v) = self.aliases.get(token) {
+            return Some(v);
+        }
+        let digit = token.strip_prefix('v').or(token.strip_prefix('V'))?;
+        let v = u8::from_str_radix(digit, 16).ok()?;
+        (digit.len() == 1).then_some(v)
+    }
+
+    fn expect_register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.register(token)
+            .ok_or(format!("expected a register, got \"{token}\""))
+    }
+
+    // A number, or the name of a constant or a label that's already been defined.
+    fn value(&mut self) -> Result<i64, String> {
+        let token = self.next()?;
+        self.lookup(token)
+            .ok_or(format!("expected a number, got \"{token}\""))
+    }
+
+    fn lookup(&self, token: &str) -> Option<i64> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|&address| i64::from(address)))
+            .or_else(|| (token == "HERE").then_some(i64::from(self.pc)))
+    }
+
+    // An address, which can be a label that comes later.
+    fn address(&mut self, long: bool) -> Result<u16, String> {
+        let token = self.next()?;
+        if let Some(value) = self.lookup(token) {
+            return if long {
+                fit_word(value)
+            } else {
+                fit_address(value)
+            };
+        }
+        if !is_name(token) {
+            return Err(format!("expected an address, got \"{token}\""));
+        }
+        Ok(self.address_of(token, long))
+    }
+
+    // The address of a label, or 0 for now if it comes later, for the instruction at pc.
+    fn address_of(&mut self, name: &'a str, long: bool) -> u16 {
+        if let Some(&address) = self.labels.get(name) {
+            return address;
+        }
+        self.fixups.push(Fixup {
+            address: self.pc,
+            name,
+            line: self.line,
+            long,
+        });
+        0
+    }
+
+    fn assignment(&mut self, v: u8) -> Result<(), String> {
+        let operator = self.next()?;
+        let token = self.next()?;
+        let v2 = self.register(token);
+        let op = match (operator, v2) {
+            (":=", Some(v2)) => Op::Mov { v, v2 },
+            (":=", None) if token == "random" => Op::Rand {
+                v,
+                lit: fit_byte(self.value()?)?,
+            },
+            (":=", None) if token == "key" => Op::GetKey(v),
+            (":=", None) if token == "delay" => Op::GetDelay(v),
+            (":=", None) => Op::MviLit {
+                v,
+                lit: self.byte(token)?,
+            },
+            ("+=", Some(v2)) => Op::Add { v, v2 },
+            ("+=", None) => Op::AdiLit {
+                v,
+                lit: self.byte(token)?,
+            },
+            ("-=", Some(v2)) => Op::Sub { v, v2 },
+            ("-=", None) => Op::AdiLit {
+                v,
+                lit: self.byte(token)?.wrapping_neg(),
+            },
+            ("=-", Some(v2)) => Op::Subb { v, v2 },
+            ("|=", Some(v2)) => Op::Or { v, v2 },
+            ("&=", Some(v2)) => Op::And { v, v2 },
+            ("^=", Some(v2)) => Op::Xor { v, v2 },
+            (">>=", Some(v2)) => Op::Shr { v, v2 },
+            ("<<=", Some(v2)) => Op::Shl { v, v2 },
+            _ => return Err(format!("can't do v{v:x} {operator} {token}")),
+        };
+        self.emit(op)?;
+        Ok(())
+    }
+
+    fn byte(&self, token: &str) -> Result<u8, String> {
+        let value = self
+            .lookup(token)
+            .ok_or(format!("expected a number, got \"{token}\""))?;
+        fit_byte(value)
+    }
+
+    fn i_assignment(&mut self) -> Result<(), String> {
+        match self.next()? {
+            ":=" => {}
+            "+=" => {
+                let v = self.expect_register()?;
+                self.emit(Op::AddI(v))?;
+                return Ok(());
+            }
+            operator => return Err(format!("can't do i {operator}")),
+        }
+        let op = match self.peek() {
+            Some("hex") => {
+                self.next()?;
+                Op::SpriteChar(self.expect_register()?)
+            }
+            Some("bighex") => {
+                self.next()?;
+                Op::BigSpriteChar(self.expect_register()?)
+            }
+            Some("long") => {
+                self.next()?;
+                Op::LongSetI(self.address(true)?)
+            }
+            _ => Op::SetI(self.address(false)?),
+        };
+        self.emit(op)?;
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let v = self.expect_register()?;
+        let comparison = self.next()?;
+        match comparison {
+            "key" => return Ok(Condition::Key(v)),
+            "-key" => return Ok(Condition::NoKey(v)),
+            _ => {}
+        }
+        let token = self.next()?;
+        let operand = match self.register(token) {
+            Some(v2) => Operand::V(v2),
+            None => Operand::Value(self.byte(token)?),
+        };
+        Ok(match comparison {
+            "==" => Condition::Eq(v, operand),
+            "!=" => Condition::Ne(v, operand),
+            "<" => Condition::Less(v, operand),
+            ">" => Condition::Greater(v, operand),
+            "<=" => Condition::LessOrEqual(v, operand),
+            ">=" => Condition::GreaterOrEqual(v, operand),
+            _ => return Err(format!("unknown comparison \"{comparison}\"")),
+        })
+    }
+
+    // Emits instructions that skip the next one when the condition is, or isn't, true.
+    fn skip_when(&mut self, condition: Condition, when: bool) -> Result<(), String> {
+        let op = match (condition, when) {
+            (Condition::Eq(v, operand), true) | (Condition::Ne(v, operand), false) => match operand
+            {
+                Operand::V(v2) => Op::SkipEq { v, v2 },
+                Operand::Value(lit) => Op::SkipEqLit { v, lit },
+            },
+            (Condition::Eq(v, operand), false) | (Condition::Ne(v, operand), true) => match operand
+            {
+                Operand::V(v2) => Op::SkipNe { v, v2 },
+                Operand::Value(lit) => Op::SkipNeLit { v, lit },
+            },
+            (Condition::Key(v), true) | (Condition::NoKey(v), false) => Op::SkipKey(v),
+            (Condition::Key(v), false) | (Condition::NoKey(v), true) => Op::SkipNoKey(v),
+            // like Octo, these subtract in vf and look at the borrow flag, 1 when vf didn't go
+            // below 0
+            (
+                Condition::Less(v, operand)
+                | Condition::Greater(v, operand)
+                | Condition::LessOrEqual(v, operand)
+                | Condition::GreaterOrEqual(v, operand),
+                _,
+            ) => {
+                self.emit(match operand {
+                    Operand::V(v2) => Op::Mov { v: 0xf, v2 },
+                    Operand::Value(lit) => Op::MviLit { v: 0xf, lit },
+                })?;
+                // vf = v - operand for < and >=, operand - v for > and <=
+                let (subtract, flag_when_true) = match condition {
+                    Condition::Less(..) => (Op::Subb { v: 0xf, v2: v }, 0),
+                    Condition::GreaterOrEqual(..) => (Op::Subb { v: 0xf, v2: v }, 1),
+                    Condition::Greater(..) => (Op::Sub { v: 0xf, v2: v }, 0),
+                    _ => (Op::Sub { v: 0xf, v2: v }, 1),
+                };
+                self.emit(subtract)?;
+                return self.skip_when(Condition::Eq(0xf, Operand::Value(flag_when_true)), when);
+            }
+        };
+        self.emit(op)
+    }
+
+    fn if_(&mut self) -> Result<(), String> {
+        let condition = self.condition()?;
+        match self.next()? {
+            "then" => self.skip_when(condition, false),
+            "begin" => {
+                self.skip_when(condition, true)?;
+                self.blocks.push(Block::If { jump: self.pc });
+                self.emit(Op::Jump(0))
+            }
+            token => Err(format!("expected then or begin, got \"{token}\"")),
+        }
+    }
+
+    fn else_(&mut self) -> Result<(), String> {
+        let Some(Block::If { jump }) = self.blocks.pop() else {
+            return Err(String::from("else without if ... begin"));
+        };
+        self.blocks.push(Block::Else { jump: self.pc });
+        self.emit(Op::Jump(0))?;
+        self.patch_jump(jump, self.pc)
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.blocks.pop() {
+            Some(Block::If { jump } | Block::Else { jump }) => self.patch_jump(jump, self.pc),
+            _ => Err(String::from("end without if ... begin")),
+        }
+    }
+
+    fn while_(&mut self) -> Result<(), String> {
+        let condition = self.condition()?;
+        self.skip_when(condition, true)?;
+        let jump = self.pc;
+        match self.blocks.iter_mut().rev().find_map(|block| match block {
+            Block::Loop { whiles, .. } => Some(whiles),
+            _ => None,
+        }) {
+            Some(whiles) => whiles.push(jump),
+            None => return Err(String::from("while outside of loop")),
+        }
+        self.emit(Op::Jump(0))
+    }
+
+    fn again(&mut self) -> Result<(), String> {
+        let Some(Block::Loop { start, whiles }) = self.blocks.pop() else {
+            return Err(String::from("again without loop"));
+        };
+        self.emit(Op::Jump(start))?;
+        for jump in whiles {
+            self.patch_jump(jump, self.pc)?;
+        }
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, jump: u16, target: u16) -> Result<(), String> {
+        let offset = usize::from(jump - ROM_START);
+        let bytes = Op::Jump(fit_address(i64::from(target))?).encode();
+        self.rom[offset..offset + 2].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            match self.next()? {
+                "{" => break,
+                param => params.push(param),
+            }
+        }
+        let body = self.braced()?;
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    // Tokens up to the } matching a { that was just taken.
+    fn braced(&mut self) -> Result<Vec<Token<'a>>, String> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or_else(|| String::from("{ without }"))?;
+            self.line = token.line;
+            match token.text {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > 100_000 {
+            return Err(format!("{name} keeps expanding forever"));
+        }
+        let line = self.line;
+        let param_count = self.macros[name].params.len();
+        let mut args = Vec::with_capacity(param_count);
+        for _ in 0..param_count {
+            args.push(self.next()?);
+        }
+        let macro_ = &self.macros[name];
+        let expanded: Vec<Token> = macro_
+            .body
+            .iter()
+            .map(|token| {
+                let text = match macro_.params.iter().position(|&param| param == token.text) {
+                    Some(index) => args[index],
+                    None => token.text,
+                };
+                // errors inside the macro point at where it was used
+                Token { text, line }
+            })
+            .collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // Evaluates { ... }. Like Octo, operators all have the same precedence and are worked
+    // out from right to left, so `2 * 3 + 4` is 14. Parentheses group things.
+    fn calc(&mut self) -> Result<i64, String> {
+        self.expect("{")?;
+        let tokens: Vec<&str> = self.braced()?.iter().map(|token| token.text).collect();
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position < tokens.len() {
+            return Err(format!("unexpected \"{}\" in :calc", tokens[position]));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens: &[&str], position: &mut usize) -> Result<i64, String> {
+        let left = self.term(tokens, position)?;
+        let Some(&operator) = tokens.get(*position) else {
+            return Ok(left);
+        };
+        if operator == ")" {
+            return Ok(left);
+        }
+        *position += 1;
+        let right = self.expression(tokens, position)?;
+        let value = match operator {
+            "+" => left.checked_add(right),
+            "-" => left.checked_sub(right),
+            "*" => left.checked_mul(right),
+            "/" => left.checked_div_euclid(right),
+            "%" => left.checked_rem_euclid(right),
+            "&" => Some(left & right),
+            "|" => Some(left | right),
+            "^" => Some(left ^ right),
+            "<<" => u32::try_from(right)
+                .ok()
+                .and_then(|right| left.checked_shl(right)),
+            ">>" => u32::try_from(right)
+                .ok()
+                .and_then(|right| left.checked_shr(right)),
+            _ => return Err(format!("unknown operator \"{operator}\" in :calc")),
+        };
+        value.ok_or(format!("{left} {operator} {right} can't be worked out"))
+    }
+
+    fn term(&self, tokens: &[&str], position: &mut usize) -> Result<i64, String> {
+        let Some(&token) = tokens.get(*position) else {
+            return Err(String::from("expected a value in :calc"));
+        };
+        *position += 1;
+        match token {
+            "(" => {
+                let value = self.expression(tokens, position)?;
+                if tokens.get(*position) != Some(&")") {
+                    return Err(String::from("( without ) in :calc"));
+                }
+                *position += 1;
+                Ok(value)
+            }
+            "-" => Ok(-self.term(tokens, position)?),
+            "~" => Ok(!self.term(tokens, position)?),
+            _ => self
+                .lookup(token)
+                .ok_or(format!("\"{token}\" isn't defined")),
+        }
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || "_-.".contains(char))
+}
+
+// Decimal, or hex after 0x, or binary after 0b, with an optional minus sign.
+fn parse_number(token: &str) -> Option<i64> {
+    let (sign, digits) = match token.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else if digits.starts_with(|char: char| char.is_ascii_digit()) {
+        digits.parse()
+    } else {
+        return None;
+    };
+    value.ok().map(|value| sign * value)
+}
+
+fn fit_nibble(value: i64) -> Result<u8, String> {
+    u8::try_from(value)
+        .ok()
+        .filter(|&nibble| nibble < 0x10)
+        .ok_or(format!("{value} doesn't fit in 4 bits"))
+}
+
+// Negative bytes wrap around, so -1 is 0xff.
+fn fit_byte(value: i64) -> Result<u8, String> {
+    if (-0x80..0x100).contains(&value) {
+        Ok(u8::try_from(value.rem_euclid(0x100)).unwrap())
+    } else {
+        Err(format!("{value} doesn't fit in a byte"))
+    }
+}
+
+fn fit_address(value: i64) -> Result<u16, String> {
+    u16::try_from(value)
+        .ok()
+        .filter(|&address| address < 0x1000)
+        .ok_or(format!("0x{value:x} doesn't fit in 12 bits"))
+}
+
+fn fit_word(value: i64) -> Result<u16, String> {
+    u16::try_from(value).map_err(|_| format!("{value} doesn't fit in 16 bits"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::State;
+
+    #[test]
+    fn compiles_control_flow() {
+        let source = "
+            :alias counter v3
+            :const LIMIT 3
+            :calc DOUBLE { LIMIT * 2 }
+            :macro twice reg { reg += 1 reg += 1 }
+
+            : main
+                counter := 0
+                loop
+                    twice counter
+                    if counter >= DOUBLE then jump done
+                again
+            : done
+                if counter == DOUBLE begin
+                    v0 := 1
+                else
+                    v0 := 2
+                end
+                i := box
+                exit
+            : box
+                0xff 0b10000001 # data
+        ";
+        let rom = compile_octo(source).unwrap();
+        let mut state = State::new();
+        state.set_platform(crate::chip8::Platform::SuperChip);
+        state.load_rom(&rom).unwrap();
+        while state.halted().is_none() {
+            state.emulate().unwrap();
+        }
+        assert_eq!((6, 1), (state.v[3], state.v[0]));
+        assert_eq!(&[0xff, 0x81], &state.memory[state.i() as usize..][..2]);
+        // main came first, so there's no jump to it
+        assert_eq!([0x63, 0x00], rom[..2]);
+    }
+
+    #[test]
+    fn statements_round_trip() {
+        for opcode in 0..=u16::MAX {
+            let [byte1, byte2] = opcode.to_be_bytes();
+            let Some(op) = Op::new(byte1, byte2) else {
+                continue;
+            };
+            let source = format!(": main {}", octo_statement(op, |_| None));
+            let rom = compile_octo(&source).unwrap_or_else(|error| panic!("{source}: {error}"));
+            assert_eq!(
+                Some(op.encode()),
+                rom.get(..2).map(<[u8]>::to_vec),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| compile_octo(source).unwrap_err().to_string();
+        assert_eq!(
+            "line 2: \"nowhere\" isn't defined",
+            error(": main\njump nowhere")
+        );
+        assert_eq!("line 1: \"main\" isn't defined", error("v0 := 1"));
+        assert_eq!("line 3: loop without again", error(": main\nloop\nv0 += 1"));
+        assert_eq!(
+            "line 1: 300 doesn't fit in a byte",
+            error(": main v0 := 300")
+        );
+    }
+}
@@ -1,12 +1,23 @@
-#[derive(Debug)]
+use super::platform::Platform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Op {
     Cls,
     Rts,
+    ScrollDown(u8),
+    ScrollUp(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    Lores,
+    Hires,
     Jump(u16),
     Call(u16),
     SkipEqLit { v: u8, lit: u8 },
     SkipNeLit { v: u8, lit: u8 },
     SkipEq { v: u8, v2: u8 },
+    RegDumpRange { v: u8, v2: u8 },
+    RegLoadRange { v: u8, v2: u8 },
     MviLit { v: u8, lit: u8 },
     AdiLit { v: u8, lit: u8 },
     Mov { v: u8, v2: u8 },
@@ -15,11 +26,12 @@ pub enum Op {
     Xor { v: u8, v2: u8 },
     Add { v: u8, v2: u8 },
     Sub { v: u8, v2: u8 },
-    Shr(u8),
+    Shr { v: u8, v2: u8 },
     Subb { v: u8, v2: u8 },
-    Shl(u8),
+    Shl { v: u8, v2: u8 },
     SkipNe { v: u8, v2: u8 },
     SetI(u16),
+    LongSetI(u16),
     JumpPlusV0(u16),
     Rand { v: u8, lit: u8 },
     Draw { v: u8, v2: u8, lit: u8 },
@@ -31,9 +43,15 @@ pub enum Op {
     Sound(u8),
     AddI(u8),
     SpriteChar(u8),
+    BigSpriteChar(u8),
     MovBcd(u8),
     RegDump(u8),
     RegLoad(u8),
+    FlagsDump(u8),
+    FlagsLoad(u8),
+    Plane(u8),
+    Audio,
+    Pitch(u8),
 }
 
 // Get the 12-bit address part of a 16-bit opcode.
@@ -46,25 +64,50 @@ fn addr_from_opcode(byte1: u8, byte2: u8) -> u16 {
 }
 
 impl Op {
-    pub fn new(byte1: u8, byte2: u8) -> Result<Self, String> {
-        let bad_instruction_error = Err(format!("bad instruction: {byte1:02x} {byte2:02x}"));
-        
+    // Decodes the instruction at the start of `code`. Every instruction is 2 bytes long,
+    // except for XO-CHIP's long I load which takes its address from the 2 bytes after it.
+    #[must_use]
+    pub fn decode(code: &[u8]) -> Option<Self> {
+        match code {
+            [0xf0, 0x00, address_high, address_low, ..] => {
+                Some(Op::LongSetI(u16::from_be_bytes([
+                    *address_high,
+                    *address_low,
+                ])))
+            }
+            [byte1, byte2, ..] => Op::new(*byte1, *byte2),
+            _ => None,
+        }
+    }
+
+    // Returns None for bytes that aren't an instruction.
+    #[must_use]
+    pub fn new(byte1: u8, byte2: u8) -> Option<Self> {
         let address = addr_from_opcode(byte1, byte2);
         let lit = byte2;
-        
+
         let nibble1 = byte1 >> 4;
         let nibble2 = byte1 & 0x0f;
         let nibble3 = byte2 >> 4;
         let nibble4 = byte2 & 0x0f;
-        
+
         let op = match (nibble1, nibble2, nibble3, nibble4) {
             (0x0, _, 0xe, 0x0) => Op::Cls,
             (0x0, _, 0xe, 0xe) => Op::Rts,
+            (0x0, 0x0, 0xc, n) => Op::ScrollDown(n),
+            (0x0, 0x0, 0xd, n) => Op::ScrollUp(n),
+            (0x0, 0x0, 0xf, 0xb) => Op::ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => Op::ScrollLeft,
+            (0x0, 0x0, 0xf, 0xd) => Op::Exit,
+            (0x0, 0x0, 0xf, 0xe) => Op::Lores,
+            (0x0, 0x0, 0xf, 0xf) => Op::Hires,
             (0x1, ..) => Op::Jump(address),
             (0x2, ..) => Op::Call(address),
-            (0x3, v, ..)  => Op::SkipEqLit { v, lit },
+            (0x3, v, ..) => Op::SkipEqLit { v, lit },
             (0x4, v, ..) => Op::SkipNeLit { v, lit },
-            (0x5, v, v2, _) => Op::SkipEq { v, v2 },
+            (0x5, v, v2, 0x0) => Op::SkipEq { v, v2 },
+            (0x5, v, v2, 0x2) => Op::RegDumpRange { v, v2 },
+            (0x5, v, v2, 0x3) => Op::RegLoadRange { v, v2 },
             (0x6, v, ..) => Op::MviLit { v, lit },
             (0x7, v, ..) => Op::AdiLit { v, lit },
             (0x8, v, v2, 0x0) => Op::Mov { v, v2 },
@@ -73,9 +116,9 @@ impl Op {
             (0x8, v, v2, 0x3) => Op::Xor { v, v2 },
             (0x8, v, v2, 0x4) => Op::Add { v, v2 },
             (0x8, v, v2, 0x5) => Op::Sub { v, v2 },
-            (0x8, v, _, 0x6) => Op::Shr(v),
+            (0x8, v, v2, 0x6) => Op::Shr { v, v2 },
             (0x8, v, v2, 0x7) => Op::Subb { v, v2 },
-            (0x8, v, _, 0xe) => Op::Shl(v),
+            (0x8, v, v2, 0xe) => Op::Shl { v, v2 },
             (0x9, v, v2, ..) => Op::SkipNe { v, v2 },
             (0xa, ..) => Op::SetI(address),
             (0xb, ..) => Op::JumpPlusV0(address),
@@ -83,40 +126,164 @@ impl Op {
             (0xd, v, v2, lit) => Op::Draw { v, v2, lit },
             (0xe, v, 0x9, 0xe) => Op::SkipKey(v),
             (0xe, v, 0xa, 0x1) => Op::SkipNoKey(v),
+            (0xf, n, 0x0, 0x1) => Op::Plane(n),
+            (0xf, 0x0, 0x0, 0x2) => Op::Audio,
             (0xf, v, 0x0, 0x7) => Op::GetDelay(v),
             (0xf, v, 0x0, 0xa) => Op::GetKey(v),
             (0xf, v, 0x1, 0x5) => Op::Delay(v),
             (0xf, v, 0x1, 0x8) => Op::Sound(v),
             (0xf, v, 0x1, 0xe) => Op::AddI(v),
             (0xf, v, 0x2, 0x9) => Op::SpriteChar(v),
+            (0xf, v, 0x3, 0x0) => Op::BigSpriteChar(v),
             (0xf, v, 0x3, 0x3) => Op::MovBcd(v),
+            (0xf, v, 0x3, 0xa) => Op::Pitch(v),
             (0xf, v, 0x5, 0x5) => Op::RegDump(v),
             (0xf, v, 0x6, 0x5) => Op::RegLoad(v),
-            _ => bad_instruction_error?,
+            (0xf, v, 0x7, 0x5) => Op::FlagsDump(v),
+            (0xf, v, 0x8, 0x5) => Op::FlagsLoad(v),
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    // The bytes new() and decode() read back as this op. Bits the decoder ignores are 0,
+    // so e.g. 0x9121 decodes to SkipNe but encodes back as 0x9120.
+    #[must_use]
+    pub fn encode(self) -> Vec<u8> {
+        let opcode = |nibble1: u8, v: u8, byte2: u8| vec![nibble1 << 4 | v & 0x0f, byte2];
+        let with_address = |nibble1: u8, address: u16| {
+            let [high, low] = address.to_be_bytes();
+            vec![nibble1 << 4 | high & 0x0f, low]
         };
-        Ok(op)
+        let registers = |nibble1: u8, v: u8, v2: u8, nibble4: u8| {
+            opcode(nibble1, v, (v2 & 0x0f) << 4 | nibble4 & 0x0f)
+        };
+        match self {
+            Op::Cls => vec![0x00, 0xe0],
+            Op::Rts => vec![0x00, 0xee],
+            Op::ScrollDown(n) => opcode(0x0, 0x0, 0xc0 | n & 0x0f),
+            Op::ScrollUp(n) => opcode(0x0, 0x0, 0xd0 | n & 0x0f),
+            Op::ScrollRight => vec![0x00, 0xfb],
+            Op::ScrollLeft => vec![0x00, 0xfc],
+            Op::Exit => vec![0x00, 0xfd],
+            Op::Lores => vec![0x00, 0xfe],
+            Op::Hires => vec![0x00, 0xff],
+            Op::Jump(address) => with_address(0x1, address),
+            Op::Call(address) => with_address(0x2, address),
+            Op::SkipEqLit { v, lit } => opcode(0x3, v, lit),
+            Op::SkipNeLit { v, lit } => opcode(0x4, v, lit),
+            Op::SkipEq { v, v2 } => registers(0x5, v, v2, 0x0),
+            Op::RegDumpRange { v, v2 } => registers(0x5, v, v2, 0x2),
+            Op::RegLoadRange { v, v2 } => registers(0x5, v, v2, 0x3),
+            Op::MviLit { v, lit } => opcode(0x6, v, lit),
+            Op::AdiLit { v, lit } => opcode(0x7, v, lit),
+            Op::Mov { v, v2 } => registers(0x8, v, v2, 0x0),
+            Op::Or { v, v2 } => registers(0x8, v, v2, 0x1),
+            Op::And { v, v2 } => registers(0x8, v, v2, 0x2),
+            Op::Xor { v, v2 } => registers(0x8, v, v2, 0x3),
+            Op::Add { v, v2 } => registers(0x8, v, v2, 0x4),
+            Op::Sub { v, v2 } => registers(0x8, v, v2, 0x5),
+            Op::Shr { v, v2 } => registers(0x8, v, v2, 0x6),
+            Op::Subb { v, v2 } => registers(0x8, v, v2, 0x7),
+            Op::Shl { v, v2 } => registers(0x8, v, v2, 0xe),
+            Op::SkipNe { v, v2 } => registers(0x9, v, v2, 0x0),
+            Op::SetI(address) => with_address(0xa, address),
+            Op::LongSetI(address) => {
+                let [high, low] = address.to_be_bytes();
+                vec![0xf0, 0x00, high, low]
+            }
+            Op::JumpPlusV0(address) => with_address(0xb, address),
+            Op::Rand { v, lit } => opcode(0xc, v, lit),
+            Op::Draw { v, v2, lit } => registers(0xd, v, v2, lit),
+            Op::SkipKey(v) => opcode(0xe, v, 0x9e),
+            Op::SkipNoKey(v) => opcode(0xe, v, 0xa1),
+            Op::Plane(n) => opcode(0xf, n, 0x01),
+            Op::Audio => vec![0xf0, 0x02],
+            Op::GetDelay(v) => opcode(0xf, v, 0x07),
+            Op::GetKey(v) => opcode(0xf, v, 0x0a),
+            Op::Delay(v) => opcode(0xf, v, 0x15),
+            Op::Sound(v) => opcode(0xf, v, 0x18),
+            Op::AddI(v) => opcode(0xf, v, 0x1e),
+            Op::SpriteChar(v) => opcode(0xf, v, 0x29),
+            Op::BigSpriteChar(v) => opcode(0xf, v, 0x30),
+            Op::MovBcd(v) => opcode(0xf, v, 0x33),
+            Op::Pitch(v) => opcode(0xf, v, 0x3a),
+            Op::RegDump(v) => opcode(0xf, v, 0x55),
+            Op::RegLoad(v) => opcode(0xf, v, 0x65),
+            Op::FlagsDump(v) => opcode(0xf, v, 0x75),
+            Op::FlagsLoad(v) => opcode(0xf, v, 0x85),
+        }
     }
 
-    fn instruction(&self) -> &'static str {
+    // Bytes the instruction takes up.
+    #[must_use]
+    pub fn size(&self) -> u16 {
+        match self {
+            Op::LongSetI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // The oldest platform that has this instruction.
+    #[must_use]
+    pub fn platform(&self) -> Platform {
+        match self {
+            Op::ScrollDown(_)
+            | Op::ScrollRight
+            | Op::ScrollLeft
+            | Op::Exit
+            | Op::Lores
+            | Op::Hires
+            | Op::BigSpriteChar(_)
+            | Op::FlagsDump(_)
+            | Op::FlagsLoad(_) => Platform::SuperChip,
+            Op::ScrollUp(_)
+            | Op::RegDumpRange { .. }
+            | Op::RegLoadRange { .. }
+            | Op::LongSetI(_)
+            | Op::Plane(_)
+            | Op::Audio
+            | Op::Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    // The mnemonic, which several ops can share, e.g. MOV.
+    #[must_use]
+    pub fn instruction(&self) -> &'static str {
         match self {
             Op::Cls => "CLS",
             Op::Rts => "RTS",
+            Op::ScrollDown(_) => "SCROLL.D",
+            Op::ScrollUp(_) => "SCROLL.U",
+            Op::ScrollRight => "SCROLL.R",
+            Op::ScrollLeft => "SCROLL.L",
+            Op::Exit => "EXIT",
+            Op::Lores => "LORES",
+            Op::Hires => "HIRES",
             Op::Jump(_) | Op::JumpPlusV0(_) => "JUMP",
             Op::Call(_) => "CALL",
-            Op::Shr(_) => "SHR.",
-            Op::Shl(_) => "SHL.",
+            Op::Shr { .. } => "SHR.",
+            Op::Shl { .. } => "SHL.",
             Op::SetI(_) | Op::MviLit { .. } => "MVI",
+            Op::LongSetI(_) => "MVI.L",
             Op::SkipKey(_) => "SKIP.KEY",
             Op::SkipNoKey(_) => "SKIP.NOKEY",
-            Op::GetDelay(_)
-            | Op::Delay(_)
-            | Op::Sound(_)
-            | Op::Mov { .. } => "MOV",
+            Op::GetDelay(_) | Op::Delay(_) | Op::Sound(_) | Op::Mov { .. } => "MOV",
             Op::GetKey(_) => "WAITKEY",
             Op::AddI(_) => "ADD",
             Op::SpriteChar(_) => "SPRITECHAR",
+            Op::BigSpriteChar(_) => "BIGCHAR",
             Op::MovBcd(_) => "MOVBCD",
-            Op::RegDump(_) | Op::RegLoad(_) => "MOVM",
+            Op::RegDump(_)
+            | Op::RegLoad(_)
+            | Op::RegDumpRange { .. }
+            | Op::RegLoadRange { .. }
+            | Op::FlagsDump(_)
+            | Op::FlagsLoad(_) => "MOVM",
+            Op::Plane(_) => "PLANE",
+            Op::Audio => "AUDIO",
+            Op::Pitch(_) => "PITCH",
             Op::Or { .. } => "OR",
             Op::And { .. } => "AND",
             Op::Xor { .. } => "XOR",
@@ -131,10 +298,36 @@ impl Op {
         }
     }
 
+    // Every mnemonic instruction() can return, sorted.
+    #[must_use]
+    pub fn mnemonics() -> Vec<&'static str> {
+        let mut mnemonics: Vec<_> = (0..=u16::MAX)
+            .filter_map(|opcode| {
+                let [byte1, byte2] = opcode.to_be_bytes();
+                Op::new(byte1, byte2)
+            })
+            .chain([Op::LongSetI(0x0000)]) // the only one new() can't decode on its own
+            .map(|op| op.instruction())
+            .collect();
+        mnemonics.sort_unstable();
+        mnemonics.dedup();
+        mnemonics
+    }
+
+    #[must_use]
     pub fn disassemble(self) -> (String, String) {
         let instruction = self.instruction();
         let args = match self {
-            Op::Cls | Op::Rts => String::new(),
+            Op::Cls
+            | Op::Rts
+            | Op::ScrollRight
+            | Op::ScrollLeft
+            | Op::Exit
+            | Op::Lores
+            | Op::Hires
+            | Op::Audio => String::new(),
+
+            Op::ScrollDown(n) | Op::ScrollUp(n) | Op::Plane(n) => format!("#${n:01x}"),
 
             Op::Jump(address) | Op::Call(address) => format!("${address:03x}"),
 
@@ -152,15 +345,17 @@ impl Op {
             | Op::Add { v, v2 }
             | Op::Sub { v, v2 }
             | Op::Subb { v, v2 }
+            | Op::Shr { v, v2 }
+            | Op::Shl { v, v2 }
             | Op::SkipNe { v, v2 } => format!("V{v:01x}, V{v2:01x}"),
 
-            Op::Shr(v)
-            | Op::Shl(v)
-            | Op::SkipKey(v)
+            Op::SkipKey(v)
             | Op::SkipNoKey(v)
             | Op::GetKey(v)
             | Op::SpriteChar(v)
-            | Op::MovBcd(v) => format!("V{v:01x}"),
+            | Op::BigSpriteChar(v)
+            | Op::MovBcd(v)
+            | Op::Pitch(v) => format!("V{v:01x}"),
 
             Op::GetDelay(v) => format!("V{v:01x}, DELAY"),
             Op::Delay(v) => format!("DELAY, V{v:01x}"),
@@ -168,7 +363,12 @@ impl Op {
             Op::AddI(v) => format!("I, V{v:01x}"),
             Op::RegDump(v) => format!("(I), V0-V{v:01x}"),
             Op::RegLoad(v) => format!("V0-V{v:01x}, (I)"),
+            Op::RegDumpRange { v, v2 } => format!("(I), V{v:01x}-V{v2:01x}"),
+            Op::RegLoadRange { v, v2 } => format!("V{v:01x}-V{v2:01x}, (I)"),
+            Op::FlagsDump(v) => format!("R, V0-V{v:01x}"),
+            Op::FlagsLoad(v) => format!("V0-V{v:01x}, R"),
             Op::SetI(address) => format!("I, #${address:03x}"),
+            Op::LongSetI(address) => format!("I, #${address:04x}"),
             Op::JumpPlusV0(address) => format!("#${address:03x}(V0)"),
             Op::Draw { v, v2, lit } => format!("V{v:01x}, V{v2:01x}, #${lit:01x}"),
         };
@@ -188,4 +388,20 @@ mod tests {
             op.disassemble()
         );
     }
+
+    #[test]
+    fn encode_round_trips() {
+        for opcode in 0..=u16::MAX {
+            let bytes = opcode.to_be_bytes();
+            if let Some(op) = Op::new(bytes[0], bytes[1]) {
+                let encoded = op.encode();
+                assert_eq!(Some(op), Op::decode(&encoded), "{opcode:04x}");
+                // only the bits the decoder ignores may differ
+                if !matches!(op, Op::Cls | Op::Rts | Op::SkipNe { .. }) {
+                    assert_eq!(bytes.to_vec(), encoded, "{opcode:04x}");
+                }
+            }
+        }
+        assert_eq!(vec![0xf0, 0x00, 0x12, 0x34], Op::LongSetI(0x1234).encode());
+    }
 }
@@ -0,0 +1,108 @@
+// Behaviour of opcodes that were implemented differently by different
+// interpreters over the years. ROMs are usually written against one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Quirks {
+    // 8xy6/8xyE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    // How Fx55/Fx65 leave the I register afterwards.
+    pub load_store: LoadStore,
+    // Bnnn jumps to nnn + VX (where X is the high nibble of nnn) instead of nnn + V0.
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    // Sprites are cut off at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    // Fx0A finishes as soon as a key is pressed, instead of when it's released like on the
+    // COSMAC VIP.
+    pub get_key_on_press: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStore {
+    // I is incremented by X + 1, like the original COSMAC VIP.
+    IncrementByXPlusOne,
+    // I is incremented by X, like CHIP-48.
+    IncrementByX,
+    // I is left untouched, like SUPER-CHIP.
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    Modern,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [
+        Preset::CosmacVip,
+        Preset::Chip48,
+        Preset::SuperChip,
+        Preset::Modern,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::CosmacVip => "COSMAC VIP",
+            Preset::Chip48 => "CHIP-48",
+            Preset::SuperChip => "SUPER-CHIP",
+            Preset::Modern => "Modern",
+        }
+    }
+
+    #[must_use]
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Preset::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store: LoadStore::IncrementByXPlusOne,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+                get_key_on_press: false,
+            },
+            Preset::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store: LoadStore::IncrementByX,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                get_key_on_press: false,
+            },
+            Preset::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store: LoadStore::Unchanged,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                get_key_on_press: false,
+            },
+            Preset::Modern => Quirks {
+                shift_uses_vy: false,
+                load_store: LoadStore::Unchanged,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+                get_key_on_press: false,
+            },
+        }
+    }
+
+    // Returns the preset these quirks are identical to, if any.
+    #[must_use]
+    pub fn from_quirks(quirks: Quirks) -> Option<Self> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.quirks() == quirks)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Preset::Modern.quirks()
+    }
+}
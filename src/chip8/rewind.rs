@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+// Recent save states, newest last, for stepping back in time.
+// Only the newest state is kept whole, every older one is stored as the bytes
+// that differ from the state after it, which for most frames is a handful of
+// registers plus whatever memory and pixels changed.
+pub struct Rewind {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>, // Oldest first, each one turns the next state into this one.
+}
+
+// A run of bytes to overwrite at an offset.
+struct Run {
+    offset: usize,
+    bytes: Vec<u8>,
+}
+
+enum Delta {
+    Runs(Vec<Run>),
+    Full(Vec<u8>), // When the state changed size, e.g. the platform switched.
+}
+
+// Differing bytes closer together than this go in the same run.
+const RUN_GAP: usize = 8;
+
+impl Rewind {
+    // Keeps up to capacity states, a frame's worth each when pushed every frame.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.newest.is_some())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // Drops the oldest states if there are more than the new capacity.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(Delta::new(&state, previous));
+        }
+        self.newest = Some(state);
+        self.truncate();
+    }
+
+    // Removes and returns the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self
+            .deltas
+            .pop_back()
+            .map(|delta| delta.apply(newest.clone()));
+        Some(newest)
+    }
+
+    fn truncate(&mut self) {
+        while self.len() > self.capacity {
+            if self.deltas.pop_front().is_none() {
+                self.newest = None;
+            }
+        }
+    }
+}
+
+impl Delta {
+    // What turns the state `from` back into `to`.
+    fn new(from: &[u8], to: Vec<u8>) -> Self {
+        if from.len() != to.len() {
+            return Delta::Full(to);
+        }
+        let mut runs: Vec<Run> = Vec::new();
+        for (offset, (&old, &new)) in from.iter().zip(&to).enumerate() {
+            if old == new {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if offset - (run.offset + run.bytes.len()) < RUN_GAP => {
+                    let end = run.offset + run.bytes.len();
+                    run.bytes.extend_from_slice(&to[end..=offset]);
+                }
+                _ => runs.push(Run {
+                    offset,
+                    bytes: vec![new],
+                }),
+            }
+        }
+        Delta::Runs(runs)
+    }
+
+    fn apply(self, mut state: Vec<u8>) -> Vec<u8> {
+        match self {
+            Delta::Runs(runs) => {
+                for run in runs {
+                    state[run.offset..run.offset + run.bytes.len()].copy_from_slice(&run.bytes);
+                }
+                state
+            }
+            Delta::Full(previous) => previous,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_in_reverse_and_drops_oldest() {
+        let mut rewind = Rewind::new(3);
+        let mut state = vec![0x00; 64];
+        let mut pushed = Vec::new();
+        for frame in 0..5_u8 {
+            let offset = usize::from(frame);
+            state[offset] = 0xff;
+            state[40 + offset * 3] = frame;
+            rewind.push(state.clone());
+            pushed.push(state.clone());
+        }
+        rewind.push(vec![0x01; 8]); // a different size
+        pushed.push(vec![0x01; 8]);
+
+        assert_eq!(3, rewind.len());
+        for expected in pushed.iter().rev().take(3) {
+            assert_eq!(Some(expected), rewind.pop().as_ref());
+        }
+        assert_eq!(None, rewind.pop());
+    }
+}
@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::fmt;
+
+use super::error::{Chip8Error, Halt};
+use super::platform::Platform;
+use super::quirks::{LoadStore, Quirks};
+
+// Every save state starts with the magic bytes and the format version, both checked on load.
+// Bump the version whenever the layout below changes.
+pub const MAGIC: [u8; 8] = *b"CH8UWUSS";
+pub const VERSION: u16 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str), // A field has a value no state could have, e.g. sp past the stack.
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {version} isn't supported, only version {VERSION} is"
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Invalid(field) => write!(f, "save state has an invalid {field}"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+// Appends fields to a save state, multi-byte values are big-endian.
+pub(super) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn new() -> Self {
+        Self::with_header(MAGIC, VERSION)
+    }
+
+    // For other files laid out like save states, e.g. movies.
+    pub fn with_header(magic: [u8; 8], version: u16) -> Self {
+        let mut writer = Writer(Vec::new());
+        writer.bytes(&magic);
+        writer.u16(version);
+        writer
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    // Prefixed with its length, for fields whose size isn't fixed.
+    pub fn sized_bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.bytes(bytes);
+    }
+
+    pub fn platform(&mut self, platform: Platform) {
+        self.u8(match platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+    }
+
+    pub fn quirks(&mut self, quirks: Quirks) {
+        self.bool(quirks.shift_uses_vy);
+        self.u8(match quirks.load_store {
+            LoadStore::IncrementByXPlusOne => 0,
+            LoadStore::IncrementByX => 1,
+            LoadStore::Unchanged => 2,
+        });
+        self.bool(quirks.jump_uses_vx);
+        self.bool(quirks.vf_reset);
+        self.bool(quirks.clip_sprites);
+        self.bool(quirks.get_key_on_press);
+    }
+
+    pub fn halt(&mut self, halt: Option<&Halt>) {
+        match halt {
+            None => self.u8(0),
+            Some(Halt::Exited) => self.u8(1),
+            Some(Halt::Fault(error)) => {
+                self.u8(2);
+                self.error(error);
+            }
+        }
+    }
+
+    fn error(&mut self, error: &Chip8Error) {
+        match *error {
+            Chip8Error::UnknownOpcode { address, opcode } => {
+                self.u8(0);
+                self.u16(address);
+                self.u16(opcode);
+            }
+            Chip8Error::UnsupportedOpcode {
+                address,
+                opcode,
+                platform,
+            } => {
+                self.u8(1);
+                self.u16(address);
+                self.u16(opcode);
+                self.platform(platform);
+            }
+            Chip8Error::StackOverflow { address } => {
+                self.u8(2);
+                self.u16(address);
+            }
+            Chip8Error::StackUnderflow { address } => {
+                self.u8(3);
+                self.u16(address);
+            }
+            Chip8Error::MemoryOutOfBounds { address, target } => {
+                self.u8(4);
+                self.u16(address);
+                self.u64(target as u64);
+            }
+            Chip8Error::RomTooLarge { size, capacity } => {
+                self.u8(5);
+                self.u64(size as u64);
+                self.u64(capacity as u64);
+            }
+        }
+    }
+}
+
+// Reads fields back in the order the Writer wrote them.
+pub(super) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    // Checks the header, leaving the reader at the first field.
+    pub fn new(data: &'a [u8]) -> Result<Self, SaveStateError> {
+        Self::with_header(data, MAGIC, VERSION)
+    }
+
+    pub fn with_header(
+        data: &'a [u8],
+        magic: [u8; 8],
+        version: u16,
+    ) -> Result<Self, SaveStateError> {
+        let mut reader = Reader(data);
+        if reader.bytes(magic.len()) != Ok(&magic) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        match reader.u16()? {
+            current if current == version => Ok(reader),
+            version => Err(SaveStateError::UnsupportedVersion(version)),
+        }
+    }
+
+    // Fails if anything is left over, which means the state wasn't read the way it was written.
+    pub fn finish(self) -> Result<(), SaveStateError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::Invalid("length"))
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("flag")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, SaveStateError> {
+        usize::try_from(self.u64()?).map_err(|_| SaveStateError::Invalid("size"))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.0.len() < len {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn sized_bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.usize()?;
+        self.bytes(len)
+    }
+
+    pub fn platform(&mut self) -> Result<Platform, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(Platform::Chip8),
+            1 => Ok(Platform::SuperChip),
+            2 => Ok(Platform::XoChip),
+            _ => Err(SaveStateError::Invalid("platform")),
+        }
+    }
+
+    pub fn quirks(&mut self) -> Result<Quirks, SaveStateError> {
+        Ok(Quirks {
+            shift_uses_vy: self.bool()?,
+            load_store: match self.u8()? {
+                0 => LoadStore::IncrementByXPlusOne,
+                1 => LoadStore::IncrementByX,
+                2 => LoadStore::Unchanged,
+                _ => return Err(SaveStateError::Invalid("load/store quirk")),
+            },
+            jump_uses_vx: self.bool()?,
+            vf_reset: self.bool()?,
+            clip_sprites: self.bool()?,
+            get_key_on_press: self.bool()?,
+        })
+    }
+
+    pub fn halt(&mut self) -> Result<Option<Halt>, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(Halt::Exited)),
+            2 => Ok(Some(Halt::Fault(self.error()?))),
+            _ => Err(SaveStateError::Invalid("halt")),
+        }
+    }
+
+    fn error(&mut self) -> Result<Chip8Error, SaveStateError> {
+        let error = match self.u8()? {
+            0 => Chip8Error::UnknownOpcode {
+                address: self.u16()?,
+                opcode: self.u16()?,
+            },
+            1 => Chip8Error::UnsupportedOpcode {
+                address: self.u16()?,
+                opcode: self.u16()?,
+                platform: self.platform()?,
+            },
+            2 => Chip8Error::StackOverflow {
+                address: self.u16()?,
+            },
+            3 => Chip8Error::StackUnderflow {
+                address: self.u16()?,
+            },
+            4 => Chip8Error::MemoryOutOfBounds {
+                address: self.u16()?,
+                target: self.usize()?,
+            },
+            5 => Chip8Error::RomTooLarge {
+                size: self.usize()?,
+                capacity: self.usize()?,
+            },
+            _ => return Err(SaveStateError::Invalid("error")),
+        };
+        Ok(error)
+    }
+}
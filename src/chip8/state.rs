@@ -1,111 +1,554 @@
-use std::error::Error;
-
 use rand::random;
 
+use super::display::Display;
+use super::error::{Chip8Error, Halt};
 use super::op::Op;
-use crate::chip8::font::Font;
-
-pub const WIDTH: usize = 64;
-pub const HEIGHT: usize = 32;
+use super::platform::Platform;
+use super::quirks::{LoadStore, Quirks};
+use super::savestate::{Reader, SaveStateError, Writer};
+use crate::chip8::font::{BigFont, Font};
 
-const ROM_START: u16 = 0x200;
-const SCREEN_START: usize = 0xf00;
+// Where roms are loaded, and where they start running.
+pub const ROM_START: u16 = 0x200;
 const FONT_START: u16 = 0x000;
+const BIG_FONT_START: u16 = 0x050;
+const DEFAULT_PITCH: u8 = 64; // Plays the audio pattern at 4000 Hz.
 
 pub struct State {
     pub rom_loaded: bool,
+    halted: Option<Halt>,
+    platform: Platform,
+    pub quirks: Quirks,
+    rom_hash: u64,
     pub v: [u8; 16],
     i: u16,
     sp: u8,
     pub pc: u16,
     pub delay: u8,
     sound: u8,
-    pub memory: [u8; 4096],
+    pub memory: Vec<u8>, // Sized for the platform.
     stack: [u16; 16],
     pub keyboard: [bool; 16],
+    keyboard_before: [bool; 16], // As the last frame ended, to tell which keys were just pressed.
+    get_key: Option<u8>,         // Key pressed while Fx0A waits, which finishes when it's released.
+    pub display: Display,
+    rpl: [u8; 16], // SUPER-CHIP's HP-48 flag registers, these survive loading another rom.
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP's 1-bit audio samples, once the rom sets them.
+    pitch: u8,
+    seed: u64, // What the rng started from when the rom was loaded, movies replay it.
+    rng: u64,
+    frame: u64,         // Frames run since the rom was loaded.
+    writes: Vec<usize>, // Memory the last instruction wrote to.
 }
 
 impl State {
+    #[must_use]
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    #[must_use]
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let seed = random();
         State {
             rom_loaded: false,
+            halted: None,
+            platform: Platform::default(),
+            quirks,
+            rom_hash: 0,
             v: [0x00; 16],
             i: 0x00,
             sp: 0x00,
             pc: ROM_START,
             delay: 0x00,
             sound: 0x00,
-            memory: [0x00; 4096],
+            memory: vec![0x00; Platform::default().memory_size()],
             stack: [0x00; 16],
             keyboard: [false; 16],
+            keyboard_before: [false; 16],
+            get_key: None,
+            display: Display::new(),
+            rpl: [0x00; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            seed,
+            rng: seed,
+            frame: 0,
+            writes: Vec::new(),
         }
     }
 
-    fn load_font(&mut self, font: Font) {
+    #[must_use]
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // Also resizes memory to what the platform can address.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.memory.resize(platform.memory_size(), 0x00);
+        self.display
+            .set_plane_count(if platform >= Platform::XoChip { 2 } else { 1 });
+    }
+
+    fn load_font(&mut self, font: &Font, big_font: &BigFont) {
         let mut mem_index = FONT_START as usize;
-        for char in font.0 {
-            for line in char {
+        for char in &font.0 {
+            for &line in char {
+                self.memory[mem_index] = line;
+                mem_index += 1;
+            }
+        }
+
+        let mut mem_index = BIG_FONT_START as usize;
+        for char in &big_font.0 {
+            for &line in char {
                 self.memory[mem_index] = line;
                 mem_index += 1;
             }
         }
     }
 
-    // initializes state and loads rom
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        *self = Self::new();
-        self.load_font(Font::new());
-        
+    /// Initializes state and loads rom, keeping the platform, quirks and flag registers.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `RomTooLarge` if the rom doesn't fit in the platform's memory, leaving the
+    /// state as it was.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
         let rom_start_idx = ROM_START as usize;
         let rom_end_idx = rom_start_idx + rom.len();
+        if rom_end_idx > self.platform.memory_size() {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                capacity: self.platform.memory_size() - rom_start_idx,
+            });
+        }
+
+        let platform = self.platform;
+        let rpl = self.rpl;
+        *self = Self::with_quirks(self.quirks);
+        self.set_platform(platform);
+        self.rpl = rpl;
+        self.load_font(&Font::new(), &BigFont::new());
         self.memory[rom_start_idx..rom_end_idx].copy_from_slice(rom);
+        self.rom_hash = rom_hash(rom);
         self.rom_loaded = true;
+        Ok(())
+    }
+
+    // Why the cpu stopped, if it did.
+    #[must_use]
+    pub fn halted(&self) -> Option<&Halt> {
+        self.halted.as_ref()
+    }
+
+    // Counts both timers down, should be called at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
     }
 
-    fn xor_pixel(&mut self, x: usize, y: usize) -> bool {
-        // bounds check
-        let x = x % 64;
-        let y = y % 32;
+    /// Emulates one 60 Hz frame: up to `cycles_per_frame` instructions, then a timer tick.
+    /// Frontends call this 60 times a second, which keeps the emulation deterministic.
+    ///
+    /// # Errors
+    ///
+    /// Fails like `emulate`, stopping the frame at the instruction that faulted.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        for _ in 0..cycles_per_frame {
+            if !self.rom_loaded || self.halted.is_some() {
+                break;
+            }
+            self.emulate()?;
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    // Ticks the timers, and remembers the keyboard so next frame knows which keys were pressed.
+    pub fn end_frame(&mut self) {
+        self.tick_timers();
+        self.keyboard_before = self.keyboard;
+        self.frame += 1;
+    }
+
+    // How many frames have ended since the rom was loaded.
+    #[must_use]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
+    // Restarts the random numbers Cxkk draws from, the same seed always gives the same ones.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = seed;
+    }
+
+    // Whether the key went down since the last frame, and Fx0A hasn't taken the press yet.
+    #[must_use]
+    pub fn key_pressed(&self, key: usize) -> bool {
+        self.keyboard[key] && !self.keyboard_before[key]
+    }
+
+    #[must_use]
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    // Also clears a halt, so a rom can be moved past whatever stopped it.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+        self.halted = None;
+    }
+
+    #[must_use]
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    #[must_use]
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    pub fn set_sound(&mut self, sound: u8) {
+        self.sound = sound;
+    }
+
+    // Changes a byte of memory from outside the program, e.g. a debugger.
+    // Returns false if there's no such address.
+    pub fn poke(&mut self, address: usize, value: u8) -> bool {
+        let Some(byte) = self.memory.get_mut(address) else {
+            return false;
+        };
+        *byte = value;
+        true
+    }
+
+    // Return addresses of the subroutines being run, innermost last.
+    #[must_use]
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    // The XO-CHIP sound, played at 4000 * 2^((pitch - 64) / 48) samples per second.
+    #[must_use]
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    #[must_use]
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    #[must_use]
+    pub fn rpl(&self) -> [u8; 16] {
+        self.rpl
+    }
+
+    pub(super) fn set_rpl(&mut self, rpl: [u8; 16]) {
+        self.rpl = rpl;
+    }
+
+    // Identifies the loaded rom, e.g. to remember per-rom settings.
+    #[must_use]
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    // Snapshots the whole machine, including memory and the loaded rom, see savestate.rs.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.u64(self.rom_hash);
+        writer.bool(self.rom_loaded);
+        writer.halt(self.halted.as_ref());
+        writer.platform(self.platform);
+        writer.quirks(self.quirks);
+        writer.bytes(&self.v);
+        writer.u16(self.i);
+        writer.u8(self.sp);
+        writer.u16(self.pc);
+        writer.u8(self.delay);
+        writer.u8(self.sound);
+        for address in self.stack {
+            writer.u16(address);
+        }
+        for pressed in self.keyboard.into_iter().chain(self.keyboard_before) {
+            writer.bool(pressed);
+        }
+        writer.bool(self.get_key.is_some());
+        writer.u8(self.get_key.unwrap_or_default());
+        writer.bytes(&self.rpl);
+        writer.bool(self.audio_pattern.is_some());
+        writer.bytes(&self.audio_pattern.unwrap_or_default());
+        writer.u8(self.pitch);
+        writer.u64(self.seed);
+        writer.u64(self.rng);
+        writer.u64(self.frame);
+        self.display.save(&mut writer);
+        writer.sized_bytes(&self.memory);
+        writer.finish()
+    }
+
+    /// Restores a snapshot from `save_state()`. The state is left alone if it can't be read.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the data isn't a save state from this version, or is truncated or corrupt.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = Reader::new(data)?;
+        let rom_hash = reader.u64()?;
+        let rom_loaded = reader.bool()?;
+        let halted = reader.halt()?;
+        let platform = reader.platform()?;
+        let quirks = reader.quirks()?;
+        let v = reader.array()?;
+        let i = reader.u16()?;
+        let sp = reader.u8()?;
+        let pc = reader.u16()?;
+        let delay = reader.u8()?;
+        let sound = reader.u8()?;
+        let mut stack = [0x00; 16];
+        for address in &mut stack {
+            *address = reader.u16()?;
+        }
+        let mut keyboard = [false; 16];
+        let mut keyboard_before = [false; 16];
+        for pressed in keyboard.iter_mut().chain(&mut keyboard_before) {
+            *pressed = reader.bool()?;
+        }
+        let waiting_for_release = reader.bool()?;
+        let get_key = Some(reader.u8()?).filter(|_| waiting_for_release);
+        let rpl = reader.array()?;
+        let has_audio_pattern = reader.bool()?;
+        let audio_pattern = Some(reader.array()?).filter(|_| has_audio_pattern);
+        let pitch = reader.u8()?;
+        let seed = reader.u64()?;
+        let rng = reader.u64()?;
+        let frame = reader.u64()?;
+        let display = Display::load(&mut reader)?;
+        let memory = reader.sized_bytes()?.to_vec();
+        reader.finish()?;
+
+        if sp as usize > stack.len() {
+            return Err(SaveStateError::Invalid("stack pointer"));
+        }
+        if memory.len() != platform.memory_size() {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        if get_key.is_some_and(|key| key > 0xf) {
+            return Err(SaveStateError::Invalid("key"));
+        }
+        *self = State {
+            rom_loaded,
+            halted,
+            platform,
+            quirks,
+            rom_hash,
+            v,
+            i,
+            sp,
+            pc,
+            delay,
+            sound,
+            memory,
+            stack,
+            keyboard,
+            keyboard_before,
+            get_key,
+            display,
+            rpl,
+            audio_pattern,
+            pitch,
+            seed,
+            rng,
+            frame,
+            writes: Vec::new(),
+        };
+        Ok(())
+    }
+
+    // Draws the sprite at I to every selected plane, the data for each plane following the last.
+    // Returns whether any pixel got turned off.
+    fn draw_sprite(&mut self, x: usize, y: usize, lit: u8) -> Result<bool, Chip8Error> {
         let mut flipped = false;
+        // SUPER-CHIP draws a 16x16 sprite when the height is 0, two bytes per row
+        let (rows, columns) = if lit == 0 && self.platform >= Platform::SuperChip {
+            (16, 16)
+        } else {
+            (lit as usize, 8)
+        };
+        let bytes_per_row = columns / 8;
+        // the starting position always wraps, only the sprite itself can get clipped
+        let (width, height) = (self.display.width(), self.display.height());
+        let start_x = x % width;
+        let start_y = y % height;
+        let mut sprite_address = self.i as usize;
+        for plane in [0b01, 0b10] {
+            if self.display.planes() & plane == 0 {
+                continue;
+            }
+            for row in 0..rows {
+                for column in 0..columns {
+                    let address = sprite_address + row * bytes_per_row + column / 8;
+                    let pixel = self.read_byte(address)? << (column % 8) & 0b1000_0000;
+                    if pixel != 0 {
+                        let x = start_x + column;
+                        let y = start_y + row;
+                        if self.quirks.clip_sprites && (x >= width || y >= height) {
+                            continue;
+                        }
+
+                        if self.display.xor_pixel(x, y, plane) {
+                            flipped = true;
+                        }
+                    }
+                }
+            }
+            sprite_address += rows * bytes_per_row;
+        }
+        Ok(flipped)
+    }
+
+    // Skips the next instruction, which is 4 bytes long if it's an XO-CHIP long I load.
+    fn skip_next(&mut self) {
+        let next = self.pc as usize + 2;
+        if self.platform >= Platform::XoChip
+            && self.memory.get(next..next + 2) == Some(&[0xf0, 0x00])
+        {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Memory access on behalf of the instruction at pc.
+    fn read_byte(&self, target: usize) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(target)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds {
+                address: self.pc,
+                target,
+            })
+    }
+
+    fn write_byte(&mut self, target: usize, value: u8) -> Result<(), Chip8Error> {
+        let address = self.pc;
+        let byte = self
+            .memory
+            .get_mut(target)
+            .ok_or(Chip8Error::MemoryOutOfBounds { address, target })?;
+        *byte = value;
+        self.writes.push(target);
+        Ok(())
+    }
 
-        // get the address of the byte where the pixel should be drawn
-        let address = SCREEN_START + ((y * 64) + x) / 8;
-        // get the amount of bits we need to shift to the right
-        let pixel_bit_offset = ((y * 64) + x) % 8;
-        let pixel_byte = &mut self.memory[address];
+    // Addresses the last instruction wrote to, for watchpoints.
+    #[must_use]
+    pub fn writes(&self) -> &[usize] {
+        &self.writes
+    }
 
-        if (*pixel_byte << pixel_bit_offset) & 0b1000_0000 != 0 {
-            flipped = true;
+    /// The instruction at pc, if it's one the platform has.
+    ///
+    /// # Errors
+    ///
+    /// Fails if pc is past the end of memory, or the opcode is unknown or not on the platform.
+    pub fn fetch(&self) -> Result<Op, Chip8Error> {
+        let address = self.pc;
+        let code = self.memory.get(address as usize..).unwrap_or_default();
+        let [byte1, byte2, ..] = *code else {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                address,
+                target: address as usize + 1,
+            });
+        };
+        let opcode = u16::from_be_bytes([byte1, byte2]);
+        let op = Op::decode(code).ok_or(Chip8Error::UnknownOpcode { address, opcode })?;
+        if op.platform() > self.platform {
+            return Err(Chip8Error::UnsupportedOpcode {
+                address,
+                opcode,
+                platform: self.platform,
+            });
         }
-        // shift the bit into position and then XOR it to the byte
-        *pixel_byte ^= 0b1000_0000 >> pixel_bit_offset;
+        Ok(op)
+    }
 
-        flipped
+    /// Runs one instruction. Errors halt the cpu, see `halted()`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the instruction faults, e.g. on an unknown opcode or a stack overflow.
+    pub fn emulate(&mut self) -> Result<(), Chip8Error> {
+        self.writes.clear();
+        let result = self.execute();
+        if let Err(error) = &result {
+            self.halted = Some(Halt::Fault(error.clone()));
+        }
+        result
     }
 
-    pub fn emulate(&mut self) -> Result<(), Box<dyn Error>> {
-        let op_byte1 = self.memory[self.pc as usize];
-        let op_byte2 = self.memory[self.pc as usize + 1];
-        let op = Op::new(op_byte1, op_byte2)?;
+    #[allow(clippy::too_many_lines)]
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        let op = self.fetch()?;
 
         match op {
             Op::Cls => {
-                for (i, byte) in self.memory[SCREEN_START..].iter_mut().enumerate() {
-                    if i >= 256 {
-                        break;
-                    }
-                    *byte = 0x00;
-                }
-                self.pc += 2;
+                self.display.clear();
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::ScrollDown(rows) => {
+                self.display.scroll(0, rows as isize);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::ScrollUp(rows) => {
+                self.display.scroll(0, -(rows as isize));
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::ScrollRight => {
+                self.display.scroll(4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::ScrollLeft => {
+                self.display.scroll(-4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::Exit => self.halted = Some(Halt::Exited),
+            Op::Lores => {
+                self.display.set_hires(false);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::Hires => {
+                self.display.set_hires(true);
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Rts => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { address: self.pc });
+                }
                 self.sp -= 1; // Decrement stack pointer.
                 self.pc = self.stack[self.sp as usize];
             }
             Op::Jump(address) => self.pc = address,
             Op::Call(address) => {
-                self.pc += 2; // Increment program counter to get next instruction.
+                if self.sp as usize == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { address: self.pc });
+                }
+                self.pc = self.pc.wrapping_add(2); // Increment program counter to get next instruction.
                 self.stack[self.sp as usize] = self.pc; // Push next instruction to the stack.
                 self.sp += 1; // Increment stack pointer.
 
@@ -113,51 +556,73 @@ impl State {
             }
             Op::SkipEqLit { v, lit } => {
                 if self.v[v as usize] == lit {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SkipNeLit { v, lit } => {
                 if self.v[v as usize] != lit {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SkipEq { v, v2 } => {
                 if self.v[v as usize] == self.v[v2 as usize] {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::RegDumpRange { v, v2 } => {
+                // works in either direction, I isn't incremented
+                for (offset, v) in register_range(v, v2).enumerate() {
+                    self.write_byte(self.i as usize + offset, self.v[v])?;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::RegLoadRange { v, v2 } => {
+                for (offset, v) in register_range(v, v2).enumerate() {
+                    self.v[v] = self.read_byte(self.i as usize + offset)?;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::MviLit { v, lit } => {
                 self.v[v as usize] = lit;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::AdiLit { v, lit } => {
                 self.v[v as usize] = self.v[v as usize].wrapping_add(lit);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Mov { v, v2 } => {
                 self.v[v as usize] = self.v[v2 as usize];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Or { v, v2 } => {
                 let value = self.v[v as usize];
                 let value2 = self.v[v2 as usize];
                 self.v[v as usize] = value | value2;
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::And { v, v2 } => {
                 let value = self.v[v as usize];
                 let value2 = self.v[v2 as usize];
                 self.v[v as usize] = value & value2;
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Xor { v, v2 } => {
                 let value = self.v[v as usize];
                 let value2 = self.v[v2 as usize];
                 self.v[v as usize] = value ^ value2;
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Add { v, v2 } => {
                 let value = self.v[v as usize];
@@ -165,7 +630,7 @@ impl State {
                 let (result, overflow) = value.overflowing_add(value2);
                 self.v[v as usize] = result;
                 self.v[0xF] = u8::from(overflow);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Sub { v, v2 } => {
                 let value = self.v[v as usize];
@@ -173,13 +638,13 @@ impl State {
                 let (result, underflow) = value.overflowing_sub(value2);
                 self.v[v as usize] = result;
                 self.v[0xF] = u8::from(!underflow);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
-            Op::Shr(v) => {
-                let value = self.v[v as usize];
+            Op::Shr { v, v2 } => {
+                let value = self.shift_operand(v, v2);
                 self.v[v as usize] = value >> 1;
                 self.v[0xF] = value & 0b0000_0001;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Subb { v, v2 } => {
                 let value = self.v[v as usize];
@@ -187,90 +652,103 @@ impl State {
                 let (result, underflow) = value2.overflowing_sub(value);
                 self.v[v as usize] = result;
                 self.v[0xF] = u8::from(!underflow);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
-            Op::Shl(v) => {
-                let value = self.v[v as usize];
+            Op::Shl { v, v2 } => {
+                let value = self.shift_operand(v, v2);
                 self.v[v as usize] = value << 1;
                 self.v[0xF] = (value & 0b1000_0000) >> 7;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SkipNe { v, v2 } => {
                 if self.v[v as usize] != self.v[v2 as usize] {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SetI(address) => {
                 self.i = address;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::LongSetI(address) => {
+                self.i = address;
+                self.pc = self.pc.wrapping_add(4);
             }
             Op::JumpPlusV0(address) => {
-                self.pc = address + u16::from(self.v[0x0]);
+                // with the quirk, the high nibble of the address also selects the register
+                let v = if self.quirks.jump_uses_vx {
+                    (address >> 8) as usize
+                } else {
+                    0x0
+                };
+                self.pc = address + u16::from(self.v[v]);
             }
             Op::Rand { v, lit } => {
-                let random_byte = random::<u8>();
+                let random_byte = splitmix64(&mut self.rng).to_le_bytes()[0];
                 self.v[v as usize] = lit & random_byte;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Draw { v, v2, lit } => {
-                let mut flipped = false;
-                for row in 0..lit {
-                    let line = self.memory[self.i as usize + row as usize];
-                    for column in 0..8 {
-                        let pixel = line << column & 0b1000_0000;
-                        if pixel != 0 {
-                            let x = self.v[v as usize] as usize + column;
-                            let y = self.v[v2 as usize] as usize + row as usize;
-
-                            if self.xor_pixel(x, y) {
-                                flipped = true;
-                            }
-                        }
-                    }
-                }
+                let x = self.v[v as usize] as usize;
+                let y = self.v[v2 as usize] as usize;
+                let flipped = self.draw_sprite(x, y, lit)?;
                 self.v[0xF] = u8::from(flipped);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SkipKey(v) => {
-                let key = self.v[v as usize];
+                let key = self.v[v as usize] & 0x0f;
                 if self.keyboard[key as usize] {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SkipNoKey(v) => {
-                let key = self.v[v as usize];
+                let key = self.v[v as usize] & 0x0f;
                 if !self.keyboard[key as usize] {
-                    self.pc += 2;
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::GetDelay(v) => {
                 self.v[v as usize] = self.delay;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
+            // waits for a key to be pressed and then released, like the COSMAC VIP, or just
+            // pressed with the quirk. Keys held from before don't count.
             Op::GetKey(v) => {
-                for (key, pressed) in (0..).zip(self.keyboard.iter()) {
-                    if *pressed {
-                        self.v[v as usize] = key;
-                        self.pc += 2;
-                        break;
-                    }
+                let key = match self.get_key {
+                    Some(key) => (!self.keyboard[key as usize]).then_some(key),
+                    None => match (0u8..16).find(|&key| self.key_pressed(key as usize)) {
+                        Some(key) => {
+                            self.keyboard_before[key as usize] = true; // the press is used up
+                            if self.quirks.get_key_on_press {
+                                Some(key)
+                            } else {
+                                self.get_key = Some(key);
+                                None
+                            }
+                        }
+                        None => None,
+                    },
+                };
+                if let Some(key) = key {
+                    self.get_key = None;
+                    self.v[v as usize] = key;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Op::Delay(v) => {
                 self.delay = self.v[v as usize];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::Sound(v) => {
                 self.sound = self.v[v as usize];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::AddI(v) => {
                 let value = u16::from(self.v[v as usize]);
                 self.i = self.i.wrapping_add(value);
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::SpriteChar(v) => {
                 // get the value of v[v]
@@ -278,7 +756,12 @@ impl State {
                 // so we multiply this value by 5 and add FONT_START
                 // this is where our character's font lies in memory
                 self.i = FONT_START + u16::from(self.v[v as usize]) * 5;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::BigSpriteChar(v) => {
+                // same as above, but big characters are 10 bytes long
+                self.i = BIG_FONT_START + u16::from(self.v[v as usize] & 0x0f) * 10;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::MovBcd(v) => {
                 let value = self.v[v as usize];
@@ -286,58 +769,114 @@ impl State {
                 let tens = value / 10 % 10;
                 let hundreds = value / 10 / 10 % 10;
 
-                self.memory[self.i as usize] = hundreds;
-                self.memory[self.i as usize + 1] = tens;
-                self.memory[self.i as usize + 2] = ones;
-                self.pc += 2;
+                self.write_byte(self.i as usize, hundreds)?;
+                self.write_byte(self.i as usize + 1, tens)?;
+                self.write_byte(self.i as usize + 2, ones)?;
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::RegDump(vx) => {
                 for v in 0..=vx {
-                    self.memory[self.i as usize + v as usize] = self.v[v as usize];
+                    self.write_byte(self.i as usize + v as usize, self.v[v as usize])?;
                 }
-                self.pc += 2;
+                self.increment_i_after_load_store(vx);
+                self.pc = self.pc.wrapping_add(2);
             }
             Op::RegLoad(vx) => {
                 for v in 0..=vx {
-                    self.v[v as usize] = self.memory[self.i as usize + v as usize];
+                    self.v[v as usize] = self.read_byte(self.i as usize + v as usize)?;
                 }
-                self.pc += 2;
+                self.increment_i_after_load_store(vx);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::FlagsDump(vx) => {
+                self.rpl[..=vx as usize].copy_from_slice(&self.v[..=vx as usize]);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::FlagsLoad(vx) => {
+                self.v[..=vx as usize].copy_from_slice(&self.rpl[..=vx as usize]);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::Plane(planes) => {
+                self.display.set_planes(planes);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::Audio => {
+                let i = self.i as usize;
+                let mut pattern = [0x00; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_byte(i + offset)?;
+                }
+                self.audio_pattern = Some(pattern);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Op::Pitch(v) => {
+                self.pitch = self.v[v as usize];
+                self.pc = self.pc.wrapping_add(2);
             }
         }
 
         Ok(())
     }
 
-    pub fn frame_grayscale(&self) -> Vec<u8> {
-        let mut frame = Vec::new();
-        for i in 0..WIDTH * HEIGHT {
-            let screen_bit = {
-                let byte = SCREEN_START + i / 8;
-                let bit_offset = i % 8;
-                self.memory[byte] << bit_offset
-            };
+    // the value that gets shifted by 8xy6/8xyE
+    fn shift_operand(&self, v: u8, v2: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v[v2 as usize]
+        } else {
+            self.v[v as usize]
+        }
+    }
 
-            if screen_bit & 0b1000_0000 != 0 {
-                // draw white
-                frame.push(0xff);
-            } else {
-                // draw black
-                frame.push(0x00);
-            }
+    fn increment_i_after_load_store(&mut self, vx: u8) {
+        match self.quirks.load_store {
+            // XO-CHIP can address all of memory, so I wraps around from the top of it
+            LoadStore::IncrementByXPlusOne => self.i = self.i.wrapping_add(u16::from(vx) + 1),
+            LoadStore::IncrementByX => self.i = self.i.wrapping_add(u16::from(vx)),
+            LoadStore::Unchanged => {}
         }
-        frame
     }
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Registers from v to v2, going backwards if v2 comes first.
+fn register_range(v: u8, v2: u8) -> impl Iterator<Item = usize> {
+    let (v, v2) = (v as usize, v2 as usize);
+    (0..=v.abs_diff(v2)).map(move |offset| if v <= v2 { v + offset } else { v - offset })
+}
+
+// Steps a SplitMix64 generator, small enough to keep in save states and the same everywhere.
+fn splitmix64(rng: &mut u64) -> u64 {
+    *rng = rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *rng;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// 64-bit FNV-1a, stable across platforms and compiler versions.
+#[must_use]
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chip8::Preset;
+    use crate::chip8::display::{HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
 
     #[test]
     fn emu_set_i() {
         let rom = vec![0xa1, 0x23]; // MVI I,#$123
         let mut state = State::new();
-        state.load_rom(&rom);
+        state.load_rom(&rom).unwrap();
 
         state.emulate().unwrap();
         assert_eq!(0x0123, state.i);
@@ -351,7 +890,7 @@ mod tests {
             0xf1, 0x33, // MOVBCD V1
         ];
         let mut state = State::new();
-        state.load_rom(&rom);
+        state.load_rom(&rom).unwrap();
 
         state.emulate().unwrap();
         state.emulate().unwrap();
@@ -361,4 +900,267 @@ mod tests {
         let ones = state.memory[state.i as usize + 2];
         assert_eq!((0x01, 0x02, 0x03), (hundreds, tens, ones));
     }
+
+    #[test]
+    fn emu_shift_quirk() {
+        let rom = vec![
+            0x61, 0x01, // MVI  V1,#$01
+            0x62, 0x81, // MVI  V2,#$81
+            0x81, 0x26, // SHR. V1,V2
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            state.emulate().unwrap();
+        }
+        assert_eq!((0x00, 0x01), (state.v[0x1], state.v[0xf]));
+
+        state.quirks = Preset::CosmacVip.quirks();
+        state.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            state.emulate().unwrap();
+        }
+        assert_eq!((0x40, 0x01), (state.v[0x1], state.v[0xf]));
+    }
+
+    #[test]
+    fn emu_load_store_quirk() {
+        let rom = vec![
+            0xa3, 0x00, // MVI  I,#$300
+            0xf2, 0x55, // MOVM (I),V0-V2
+        ];
+        let mut state = State::with_quirks(Preset::CosmacVip.quirks());
+        state.load_rom(&rom).unwrap();
+        state.emulate().unwrap();
+        state.emulate().unwrap();
+        assert_eq!(0x0303, state.i);
+
+        state.quirks = Preset::Chip48.quirks();
+        state.load_rom(&rom).unwrap();
+        state.emulate().unwrap();
+        state.emulate().unwrap();
+        assert_eq!(0x0302, state.i);
+
+        let rom = vec![
+            0xf0, 0x00, 0xff, 0xff, // MVI.L I,#$ffff
+            0xf0, 0x55, // MOVM (I),V0
+        ];
+        state.quirks = Preset::CosmacVip.quirks();
+        state.set_platform(Platform::XoChip);
+        state.load_rom(&rom).unwrap();
+        state.emulate().unwrap();
+        state.emulate().unwrap();
+        assert_eq!(0x0000, state.i);
+    }
+
+    #[test]
+    fn emu_superchip_hires_scroll() {
+        let rom = vec![
+            0x00, 0xff, // HIRES
+            0x60, 0x00, // MVI        V0,#$00
+            0xf0, 0x29, // SPRITECHAR V0
+            0xd0, 0x01, // SPRITE     V0,V0,#$1
+            0x00, 0xfb, // SCROLL.R
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        assert!(state.emulate().is_err()); // not a CHIP-8 instruction
+
+        state.set_platform(Platform::SuperChip);
+        state.load_rom(&rom).unwrap();
+        for _ in 0..5 {
+            state.emulate().unwrap();
+        }
+        let frame = state.display.frame();
+        assert_eq!(HIRES_WIDTH * HIRES_HEIGHT, frame.len());
+        assert_eq!([0x00, 0x01, 0x01, 0x01, 0x01, 0x00], frame[3..9]);
+    }
+
+    #[test]
+    fn emu_xochip_planes() {
+        let rom = vec![
+            0xf0, 0x00, 0x03, 0x00, // MVI.L  I,#$0300
+            0xf3, 0x01, // PLANE  #$3
+            0x60, 0x01, // MVI    V0,#$01
+            0xd0, 0x01, // SPRITE V0,V0,#$1
+            0x50, 0x02, // MOVM   (I),V0-V0
+        ];
+        let mut state = State::new();
+        state.set_platform(Platform::XoChip);
+        state.load_rom(&rom).unwrap();
+        state.memory[0x300] = 0b1100_0000; // plane 1
+        state.memory[0x301] = 0b1010_0000; // plane 2
+        for _ in 0..5 {
+            state.emulate().unwrap();
+        }
+        assert_eq!(0x10000, state.memory.len());
+        let frame = state.display.frame();
+        assert_eq!([0b00, 0b11, 0b01, 0b10], frame[WIDTH..WIDTH + 4]);
+        assert_eq!(0x01, state.memory[0x300]);
+    }
+
+    #[test]
+    fn emu_run_frame_ticks_timers() {
+        let rom = vec![
+            0x60, 0x05, // MVI  V0,#$05
+            0xf0, 0x15, // MOV  DELAY,V0
+            0xf0, 0x18, // MOV  SOUND,V0
+            0x12, 0x06, // JUMP $206
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        state.run_frame(10).unwrap();
+        assert_eq!((0x04, 0x04), (state.delay, state.sound));
+        for _ in 0..10 {
+            state.run_frame(10).unwrap();
+        }
+        assert_eq!((0x00, 0x00), (state.delay, state.sound));
+    }
+
+    #[test]
+    fn emu_get_key_waits_for_release() {
+        let rom = vec![
+            0xf3, 0x0a, // GETKEY V3
+            0x12, 0x00, // JUMP   $200
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        state.keyboard[0x7] = true;
+        state.end_frame(); // held since an earlier frame, so it doesn't count
+        state.run_frame(4).unwrap();
+        assert_eq!(0x200, state.pc);
+
+        state.keyboard[0xa] = true;
+        state.run_frame(4).unwrap();
+        assert_eq!(0x200, state.pc); // waiting for A to be let go
+        state.keyboard[0xa] = false;
+        state.run_frame(1).unwrap();
+        assert_eq!((0x202, 0xa), (state.pc, state.v[3]));
+
+        // with the quirk the press is enough, but it's only taken once
+        state.quirks.get_key_on_press = true;
+        state.keyboard[0xc] = true;
+        state.run_frame(3).unwrap();
+        assert_eq!((0x200, 0xc), (state.pc, state.v[3]));
+        state.run_frame(1).unwrap();
+        assert_eq!(0x200, state.pc);
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let rom = vec![
+            0x60, 0x05, // MVI    V0,#$05
+            0xa3, 0x00, // MVI    I,#$300
+            0xf0, 0x33, // MOVBCD V0
+            0x22, 0x0a, // CALL   $20a
+            0x12, 0x08, // JUMP   $208
+            0x00, 0xfd, // EXIT
+        ];
+        let mut state = State::new();
+        state.set_platform(Platform::SuperChip);
+        state.load_rom(&rom).unwrap();
+        state.run_frame(4).unwrap();
+        let saved = state.save_state();
+
+        state.run_frame(4).unwrap();
+        assert_eq!(Some(&Halt::Exited), state.halted());
+        state.load_state(&saved).unwrap();
+        assert_eq!(None, state.halted());
+        assert_eq!((0x20a, 0x01), (state.pc, state.sp));
+        assert_eq!(saved, state.save_state());
+
+        let mut broken = saved.clone();
+        broken[8] = 0xff; // version
+        assert!(state.load_state(&broken).is_err());
+        assert!(state.load_state(&saved[..saved.len() - 1]).is_err());
+        assert_eq!(saved, state.save_state());
+    }
+
+    #[test]
+    fn setters() {
+        let rom = vec![
+            0x00, 0x00, // not an instruction
+            0xf0, 0x65, // MOVM V0-V0,(I)
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        assert!(state.emulate().is_err());
+        state.set_pc(0x202);
+        state.set_i(0x300);
+        assert!(state.poke(0x300, 0x42));
+        assert!(!state.poke(0x1000, 0x42));
+        state.run_frame(1).unwrap();
+        assert_eq!((None, 0x42), (state.halted(), state.v[0x0]));
+    }
+
+    #[test]
+    fn emu_errors_halt() {
+        let rom = vec![
+            0x00, 0xee, // RTS
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        let error = Chip8Error::StackUnderflow { address: 0x200 };
+        assert_eq!(Err(error.clone()), state.emulate());
+        assert_eq!(Some(&Halt::Fault(error)), state.halted());
+        state.run_frame(10).unwrap(); // a halted cpu doesn't run
+
+        let rom = vec![
+            0x00, 0x00, // not an instruction
+        ];
+        state.load_rom(&rom).unwrap();
+        assert_eq!(
+            Err(Chip8Error::UnknownOpcode {
+                address: 0x200,
+                opcode: 0x0000
+            }),
+            state.emulate()
+        );
+
+        let rom = vec![0x00; 0x1000];
+        assert_eq!(
+            Err(Chip8Error::RomTooLarge {
+                size: 0x1000,
+                capacity: 0xe00
+            }),
+            state.load_rom(&rom)
+        );
+    }
+
+    #[test]
+    fn emu_register_ranges() {
+        let rom = vec![
+            0xa3, 0x00, // MVI  I,#$300
+            0x51, 0x32, // MOVM (I),V1-V3
+            0xa3, 0x10, // MVI  I,#$310
+            0x53, 0x12, // MOVM (I),V3-V1
+            0x54, 0x63, // MOVM V4-V6,(I)
+        ];
+        let mut state = State::new();
+        state.set_platform(Platform::XoChip);
+        state.load_rom(&rom).unwrap();
+        state.v[1..=3].copy_from_slice(&[0x11, 0x22, 0x33]);
+        for _ in 0..5 {
+            state.emulate().unwrap();
+        }
+        assert_eq!([0x11, 0x22, 0x33], state.memory[0x300..0x303]);
+        assert_eq!([0x33, 0x22, 0x11], state.memory[0x310..0x313]);
+        assert_eq!([0x33, 0x22, 0x11], state.v[4..=6]);
+    }
+
+    #[test]
+    fn emu_failed_load_keeps_state() {
+        let rom = vec![
+            0x60, 0x2a, // MVI  V0,#$2a
+        ];
+        let mut state = State::new();
+        state.load_rom(&rom).unwrap();
+        state.emulate().unwrap();
+        let before = state.save_state();
+
+        assert!(state.load_rom(&[0x00; 0x1000]).is_err());
+        assert_eq!(before, state.save_state());
+        assert!(state.rom_loaded);
+        assert_eq!(rom_hash(&rom), state.rom_hash());
+    }
 }
@@ -0,0 +1,49 @@
+use gilrs::Gilrs;
+
+use super::{Button, Gamepad};
+
+const BUTTONS: [(gilrs::Button, Button); 14] = [
+    (gilrs::Button::DPadUp, Button::DPadUp),
+    (gilrs::Button::DPadDown, Button::DPadDown),
+    (gilrs::Button::DPadLeft, Button::DPadLeft),
+    (gilrs::Button::DPadRight, Button::DPadRight),
+    (gilrs::Button::South, Button::South),
+    (gilrs::Button::East, Button::East),
+    (gilrs::Button::West, Button::West),
+    (gilrs::Button::North, Button::North),
+    (gilrs::Button::LeftTrigger, Button::LeftBumper),
+    (gilrs::Button::RightTrigger, Button::RightBumper),
+    (gilrs::Button::LeftTrigger2, Button::LeftTrigger),
+    (gilrs::Button::RightTrigger2, Button::RightTrigger),
+    (gilrs::Button::Select, Button::Select),
+    (gilrs::Button::Start, Button::Start),
+];
+
+// The system's game controllers, any of them can be used.
+pub struct Controllers {
+    gilrs: Gilrs,
+}
+
+impl Controllers {
+    // None when the platform has no gamepad support.
+    #[must_use]
+    pub fn new() -> Option<Self> {
+        Gilrs::new().ok().map(|gilrs| Controllers { gilrs })
+    }
+}
+
+impl Gamepad for Controllers {
+    fn held(&mut self) -> Vec<Button> {
+        // handling the events is what keeps each gamepad's state up to date
+        while self.gilrs.next_event().is_some() {}
+        BUTTONS
+            .into_iter()
+            .filter(|&(gilrs_button, _)| {
+                self.gilrs
+                    .gamepads()
+                    .any(|(_, gamepad)| gamepad.is_pressed(gilrs_button))
+            })
+            .map(|(_, button)| button)
+            .collect()
+    }
+}
@@ -0,0 +1,165 @@
+#[cfg(feature = "gamepad")]
+mod controllers;
+#[cfg(feature = "gamepad")]
+pub use controllers::Controllers;
+
+// Gamepad buttons, named after where they are rather than what's printed on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    South, // A on Xbox pads, cross on PlayStation ones.
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 14] = [
+        Button::DPadUp,
+        Button::DPadDown,
+        Button::DPadLeft,
+        Button::DPadRight,
+        Button::South,
+        Button::East,
+        Button::West,
+        Button::North,
+        Button::LeftBumper,
+        Button::RightBumper,
+        Button::LeftTrigger,
+        Button::RightTrigger,
+        Button::Select,
+        Button::Start,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::DPadUp => "DPadUp",
+            Button::DPadDown => "DPadDown",
+            Button::DPadLeft => "DPadLeft",
+            Button::DPadRight => "DPadRight",
+            Button::South => "South",
+            Button::East => "East",
+            Button::West => "West",
+            Button::North => "North",
+            Button::LeftBumper => "LeftBumper",
+            Button::RightBumper => "RightBumper",
+            Button::LeftTrigger => "LeftTrigger",
+            Button::RightTrigger => "RightTrigger",
+            Button::Select => "Select",
+            Button::Start => "Start",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Button> {
+        Button::ALL.into_iter().find(|button| button.name() == name)
+    }
+}
+
+// Where button presses come from, e.g. the connected controllers.
+pub trait Gamepad {
+    // The buttons held right now, on any gamepad. Called once per 60 Hz frame.
+    fn held(&mut self) -> Vec<Button>;
+}
+
+// No gamepads, nothing's ever held.
+pub struct NullGamepad;
+
+impl Gamepad for NullGamepad {
+    fn held(&mut self) -> Vec<Button> {
+        Vec::new()
+    }
+}
+
+// Holds whatever it's told to, for testing.
+#[derive(Default)]
+pub struct MockGamepad {
+    pub held: Vec<Button>,
+}
+
+impl Gamepad for MockGamepad {
+    fn held(&mut self) -> Vec<Button> {
+        self.held.clone()
+    }
+}
+
+// The buttons bound to each CHIP-8 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GamepadProfile {
+    pub buttons: [Vec<Button>; 16],
+}
+
+impl GamepadProfile {
+    // No buttons bound to anything.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            buttons: [const { Vec::new() }; 16],
+        }
+    }
+
+    // Binds the button to key, and only to key.
+    pub fn bind(&mut self, key: usize, button: Button) {
+        for buttons in &mut self.buttons {
+            buttons.retain(|&bound| bound != button);
+        }
+        self.buttons[key].push(button);
+    }
+
+    // Which CHIP-8 keys the held buttons press.
+    #[must_use]
+    pub fn keyboard(&self, held: &[Button]) -> [bool; 16] {
+        std::array::from_fn(|key| self.buttons[key].iter().any(|button| held.contains(button)))
+    }
+}
+
+impl Default for GamepadProfile {
+    // The D-pad is 2/4/6/8, the directions most roms use, and the face buttons are the keys
+    // around them.
+    fn default() -> Self {
+        let mut profile = GamepadProfile::empty();
+        for (key, button) in [
+            (0x2, Button::DPadUp),
+            (0x8, Button::DPadDown),
+            (0x4, Button::DPadLeft),
+            (0x6, Button::DPadRight),
+            (0x5, Button::South),
+            (0x9, Button::East),
+            (0x7, Button::West),
+            (0x1, Button::North),
+            (0x0, Button::Select),
+            (0xf, Button::Start),
+        ] {
+            profile.bind(key, button);
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_presses_keys() {
+        let mut gamepad = MockGamepad::default();
+        let mut profile = GamepadProfile::default();
+        profile.bind(0xa, Button::South);
+        gamepad.held = vec![Button::DPadUp, Button::South, Button::LeftBumper];
+        let keyboard = profile.keyboard(&gamepad.held());
+        let held: Vec<usize> = (0..16).filter(|&key| keyboard[key]).collect();
+        assert_eq!(vec![0x2, 0xa], held);
+        assert_eq!([false; 16], profile.keyboard(&NullGamepad.held()));
+        assert_eq!(Some(Button::LeftBumper), Button::from_name("LeftBumper"));
+    }
+}
@@ -0,0 +1,4 @@
+// The emulator core, usable without the gui.
+pub mod audio;
+pub mod chip8;
+pub mod gamepad;
@@ -1,15 +1,26 @@
 use std::{thread, time};
 
 use eframe::Frame;
-use egui::{Context, Key, ViewportBuilder};
+use egui::{Context, ViewportBuilder};
+
+use chip8uwu::audio::AudioOutput;
+use chip8uwu::gamepad::Gamepad;
+use chip8uwu::{audio, chip8, gamepad};
 
-mod chip8;
 mod ui;
 
+const FRAME_DURATION: time::Duration = time::Duration::from_nanos(1_000_000_000 / 60);
+// Frames we're allowed to catch up on after a hitch, past that the emulator slows down instead.
+const MAX_FRAMES_PER_UPDATE: u32 = 4;
+
 struct App {
     chip8: chip8::State,
     gui: ui::gui::Gui,
-    delay_timer: time::Instant,
+    audio: Box<dyn AudioOutput>,
+    gamepad: Box<dyn Gamepad>,
+    last_update: time::Instant,
+    unemulated_time: time::Duration, // Time that passed which isn't a whole frame yet.
+    rewound: bool,                   // Whether the last frame went backwards.
 }
 
 impl App {
@@ -17,47 +28,84 @@ impl App {
         App {
             chip8: chip8::State::new(),
             gui: ui::gui::Gui::new(),
-            delay_timer: time::Instant::now(),
+            audio: open_audio(),
+            gamepad: open_gamepad(),
+            last_update: time::Instant::now(),
+            unemulated_time: time::Duration::ZERO,
+            rewound: false,
         }
     }
 }
 
+// Falls back to silence when there's no sound card, or no audio support compiled in.
+fn open_audio() -> Box<dyn AudioOutput> {
+    #[cfg(feature = "audio")]
+    if let Some(speaker) = audio::Speaker::new() {
+        return Box::new(speaker);
+    }
+    Box::new(audio::NullAudio)
+}
+
+// No gamepads without gamepad support compiled in, or when the platform has none.
+fn open_gamepad() -> Box<dyn Gamepad> {
+    #[cfg(feature = "gamepad")]
+    if let Some(controllers) = gamepad::Controllers::new() {
+        return Box::new(controllers);
+    }
+    Box::new(gamepad::NullGamepad)
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &Context, _: &mut Frame) {
+        let mut rewinding = false;
+        let buttons = self.gamepad.held();
+        // backspace in a text field deletes text, it doesn't rewind
+        let typing = ctx.wants_keyboard_input();
         ctx.input(|i| {
-            let keys = [
-                Key::X,
-                Key::Num1,
-                Key::Num2,
-                Key::Num3,
-                Key::Q,
-                Key::W,
-                Key::E,
-                Key::A,
-                Key::S,
-                Key::D,
-                Key::Z,
-                Key::C,
-                Key::Num4,
-                Key::R,
-                Key::F,
-                Key::V,
-            ];
-            
-            if self.delay_timer.elapsed() > time::Duration::from_millis(16) {
-                self.chip8.delay = self.chip8.delay.saturating_sub(1); // Decrement the delay register.
-                self.delay_timer = time::Instant::now();
-            }
-            self.chip8.keyboard = keys.map(|key| i.key_down(key));
-
-            let cycles_to_run = (500.0 * i.unstable_dt) as usize;
+            rewinding = !typing && i.key_down(ui::gui::REWIND_KEY);
+            self.chip8.keyboard = self.gui.keyboard(i, &buttons, self.chip8.rom_hash());
+        });
 
-            if self.chip8.rom_loaded {
-                for _ in 0..cycles_to_run {
-                    self.chip8.emulate().unwrap();
+        let now = time::Instant::now();
+        self.unemulated_time = (self.unemulated_time + (now - self.last_update))
+            .min(FRAME_DURATION * MAX_FRAMES_PER_UPDATE);
+        self.last_update = now;
+        while self.unemulated_time >= FRAME_DURATION {
+            self.unemulated_time -= FRAME_DURATION;
+            if rewinding {
+                // runs backwards a frame at a time, silently. The newest state is the frame on
+                // screen when rewinding starts, so going back starts from the one before it.
+                if !self.rewound {
+                    self.gui.rewind.pop();
+                    self.rewound = true;
+                }
+                if let Some(save_state) = self.gui.rewind.pop() {
+                    self.chip8
+                        .load_state(&save_state)
+                        .expect("rewind states are saved by this build");
                 }
+                self.audio.play_frame(None);
+                continue;
             }
-        });
+            if self.gui.debugger.paused() {
+                self.audio.play_frame(None);
+                continue;
+            }
+            self.gui.movie_frame(&mut self.chip8);
+            // errors halt the cpu, the gui shows why
+            let _ = self
+                .gui
+                .debugger
+                .run_frame(&mut self.chip8, self.gui.cycles_per_frame);
+            self.gui.rewind.push(self.chip8.save_state());
+            self.rewound = false;
+
+            let tone = self.gui.audio.tone(&self.chip8);
+            self.audio.play_frame(tone);
+            if let Some(recording) = &mut self.gui.recording {
+                recording.play_frame(tone);
+            }
+        }
 
         self.gui.ui(ctx, &mut self.chip8);
     }
@@ -73,9 +121,11 @@ fn main() {
         native_options,
         Box::new(|cc| {
             let egui_ctx = cc.egui_ctx.clone();
-            thread::spawn(move || loop {
-                thread::sleep(time::Duration::from_millis(1));
-                egui_ctx.request_repaint();
+            thread::spawn(move || {
+                loop {
+                    thread::sleep(time::Duration::from_millis(1));
+                    egui_ctx.request_repaint();
+                }
             });
             Ok(Box::new(App::new()))
         }),
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Ui};
+
+use crate::chip8::{Breakpoints, Condition, Op};
+
+pub struct BreakpointsWindow {
+    pub open: bool,
+    new_breakpoint: String,
+    new_watchpoint: String,
+    conditions: HashMap<u16, String>, // What's typed into each breakpoint's condition field.
+    mnemonics: Vec<&'static str>,
+    error: Option<String>, // Why the last thing typed in couldn't be used.
+}
+
+impl BreakpointsWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            new_breakpoint: String::new(),
+            new_watchpoint: String::new(),
+            conditions: HashMap::new(),
+            mnemonics: Op::mnemonics(),
+            error: None,
+        }
+    }
+
+    pub fn draw(&mut self, ctx: &egui::Context, breakpoints: &mut Breakpoints) {
+        let mut open = self.open;
+        egui::Window::new("Breakpoints")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong("Breakpoints");
+                self.draw_pc_breakpoints(ui, breakpoints);
+                ui.separator();
+
+                ui.strong("Watchpoints");
+                ui.label("Stop after an instruction writes to:");
+                self.draw_watchpoints(ui, breakpoints);
+                ui.separator();
+
+                ui.strong("Instructions");
+                self.draw_op_breakpoints(ui, breakpoints);
+
+                if let Some(error) = &self.error {
+                    ui.separator();
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+            });
+        self.open = open;
+    }
+
+    fn draw_pc_breakpoints(&mut self, ui: &mut Ui, breakpoints: &mut Breakpoints) {
+        // forget what was typed for breakpoints removed from the disassembler
+        self.conditions
+            .retain(|address, _| breakpoints.pc.contains_key(address));
+        let mut removed = None;
+        for (&address, condition) in &mut breakpoints.pc {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("${address:03x}"));
+                let text = self.conditions.entry(address).or_insert_with(|| {
+                    condition
+                        .as_ref()
+                        .map(Condition::to_string)
+                        .unwrap_or_default()
+                });
+                let response = ui.add(
+                    egui::TextEdit::singleline(text)
+                        .hint_text("Condition, e.g. V3 == 0x10")
+                        .desired_width(180.0),
+                );
+                if response.lost_focus() {
+                    if text.trim().is_empty() {
+                        *condition = None;
+                    } else {
+                        match text.parse() {
+                            Ok(parsed) => {
+                                *condition = Some(parsed);
+                                self.error = None;
+                            }
+                            Err(error) => self.error = Some(error),
+                        }
+                    }
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(address);
+                }
+            });
+        }
+        if let Some(address) = removed {
+            breakpoints.pc.remove(&address);
+            self.conditions.remove(&address);
+        }
+
+        if let Some(address) = add_address_field(ui, &mut self.new_breakpoint, &mut self.error) {
+            breakpoints.pc.entry(address).or_insert(None);
+        }
+    }
+
+    fn draw_watchpoints(&mut self, ui: &mut Ui, breakpoints: &mut Breakpoints) {
+        let mut removed = None;
+        for &address in &breakpoints.watch {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("${address:03x}"));
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(address);
+                }
+            });
+        }
+        if let Some(address) = removed {
+            breakpoints.watch.remove(&address);
+        }
+
+        if let Some(address) = add_address_field(ui, &mut self.new_watchpoint, &mut self.error) {
+            breakpoints.watch.insert(address);
+        }
+    }
+
+    fn draw_op_breakpoints(&mut self, ui: &mut Ui, breakpoints: &mut Breakpoints) {
+        ui.checkbox(&mut breakpoints.unknown_op, "Unknown instructions");
+        ui.label("Stop before any:");
+        egui::Grid::new("op breakpoints").show(ui, |ui| {
+            for (n, &mnemonic) in self.mnemonics.iter().enumerate() {
+                let mut enabled = breakpoints.ops.contains(mnemonic);
+                if ui.checkbox(&mut enabled, mnemonic).changed() {
+                    if enabled {
+                        breakpoints.ops.insert(mnemonic);
+                    } else {
+                        breakpoints.ops.remove(mnemonic);
+                    }
+                }
+                if n % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+    }
+}
+
+// A text field and Add button, returns the address once one is added.
+fn add_address_field(ui: &mut Ui, text: &mut String, error: &mut Option<String>) -> Option<u16> {
+    ui.horizontal(|ui| {
+        let response = ui.add(
+            egui::TextEdit::singleline(text)
+                .hint_text("Address, e.g. 0x200")
+                .desired_width(120.0),
+        );
+        let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if !(ui.button("Add").clicked() || entered) {
+            return None;
+        }
+        let trimmed = text.trim();
+        let hex = trimmed
+            .strip_prefix("0x")
+            .or(trimmed.strip_prefix('$'))
+            .unwrap_or(trimmed);
+        if let Ok(address) = u16::from_str_radix(hex, 16) {
+            text.clear();
+            *error = None;
+            Some(address)
+        } else {
+            *error = Some(format!("\"{trimmed}\" isn't a hex address"));
+            None
+        }
+    })
+    .inner
+}
@@ -1,71 +1,278 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
 use egui::TextStyle::Body;
-use egui::{Color32, FontId};
+use egui::{Color32, FontId, RichText, Sense};
 
 use crate::chip8;
+use crate::chip8::{Breakpoints, Disassembly, Line, Platform, ROM_START};
+
+const LABEL_COLOR: Color32 = Color32::from_rgb(0xff, 0xb0, 0x60);
 
 pub struct Disassembler {
     pub open: bool,
+    pub selected: Option<u16>, // Address of the line that was clicked, for run to cursor.
+    follow_pc: bool,
+    octo: bool,               // Octo syntax instead of the assembler's.
+    followed_pc: Option<u16>, // Where pc was when the list last scrolled to it.
+    listing: Option<Listing>,
+}
+
+// A disassembly of memory, kept until the code in it changes.
+struct Listing {
+    rom_hash: u64,
+    platform: Platform,
+    code: Vec<u8>,
+    // Where tracing starts: the rom's start, plus anywhere pc went that wasn't traced,
+    // like the targets of JUMP0.
+    entries: BTreeSet<u16>,
+    disassembly: Disassembly,
+    lines: Vec<Line>,
+}
+
+impl Listing {
+    fn new(chip8_state: &chip8::State, entries: BTreeSet<u16>) -> Self {
+        let code = chip8_state.memory[ROM_START as usize..].to_vec();
+        let platform = chip8_state.platform();
+        let entry_list: Vec<u16> = entries.iter().copied().collect();
+        let disassembly = Disassembly::new(&code, ROM_START, platform, &entry_list);
+        let lines = disassembly.lines(1);
+        Listing {
+            rom_hash: chip8_state.rom_hash(),
+            platform,
+            code,
+            entries,
+            disassembly,
+            lines,
+        }
+    }
+
+    // Copies what the rom wrote to its data into the lines, without tracing again.
+    // False if it wrote to code, which needs tracing again.
+    fn update_data(&mut self, memory: &[u8]) -> bool {
+        let changed: Vec<(u16, u8)> = (ROM_START..)
+            .zip(self.code.iter().zip(memory))
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(address, (_, &new))| (address, new))
+            .collect();
+        if changed
+            .iter()
+            .any(|&(address, _)| self.disassembly.is_code(address))
+        {
+            return false;
+        }
+        for (address, new) in changed {
+            self.code[usize::from(address - ROM_START)] = new;
+            // the data line the byte's on is the last one starting at or before it
+            let row = self.lines.partition_point(|line| line.address() <= address);
+            if let Some(Line::Data {
+                address: start,
+                bytes,
+            }) = self.lines[..row].last_mut()
+                && let Some(byte) = bytes.get_mut(usize::from(address - *start))
+            {
+                *byte = new;
+            }
+        }
+        true
+    }
+
+    // Row of the instruction at the address, if there is one.
+    fn row(&self, address: u16) -> Option<usize> {
+        let row = self.lines.partition_point(|line| line.address() < address);
+        self.lines[row..]
+            .iter()
+            .take_while(|line| line.address() == address)
+            .position(|line| matches!(line, Line::Instruction { .. }))
+            .map(|position| row + position)
+    }
 }
 
 impl Disassembler {
     pub fn new() -> Self {
-        Self { open: false }
+        Self {
+            open: true,
+            selected: None,
+            follow_pc: true,
+            octo: false,
+            followed_pc: None,
+            listing: None,
+        }
     }
 
-    pub fn draw(&mut self, ctx: &egui::Context, chip8_state: &chip8::State) {
+    pub fn draw(
+        &mut self,
+        ctx: &egui::Context,
+        chip8_state: &chip8::State,
+        breakpoints: &mut Breakpoints,
+    ) {
+        if !self.open {
+            return;
+        }
+        self.update_listing(chip8_state);
         egui::SidePanel::right("Disassembler")
             .default_width(300.0)
             .resizable(false)
             .show(ctx, |ui| {
-                ui.heading("Disassembler");
+                ui.horizontal(|ui| {
+                    ui.heading("Disassembler");
+                    ui.checkbox(&mut self.follow_pc, "Follow PC");
+                    ui.checkbox(&mut self.octo, "Octo");
+                });
                 ui.separator();
-                draw_list(ui, chip8_state);
+                self.draw_list(ui, chip8_state, breakpoints);
             });
     }
-}
 
-fn draw_list(ui: &mut egui::Ui, chip8_state: &chip8::State) {
-    ui.style_mut()
-        .text_styles
-        .insert(Body, FontId::monospace(11.0));
-    let row_height = ui.text_style_height(&Body);
-    ui.spacing_mut().interact_size.y = row_height;
-    ui.spacing_mut().item_spacing.y = 0.0;
-    let instructions = chip8_state.memory[0x200..].chunks_exact(2);
-    egui::ScrollArea::vertical().show_rows(ui, row_height, instructions.len(), |ui, row_range| {
-        for (i, bytes) in instructions.skip(row_range.start).enumerate() {
-            let list_pc = 0x200 + (row_range.start + i) * 2;
-            let highlighted =  chip8_state.pc == u16::try_from(list_pc).unwrap();
-                draw_line(ui, list_pc, highlighted, bytes);
-            if i > row_range.end {
-                break;
+    // Where the rom with this hash was seen running, including places tracing from its start
+    // didn't reach.
+    pub fn entries(&self, rom_hash: u64) -> Vec<u16> {
+        self.listing
+            .as_ref()
+            .filter(|listing| listing.rom_hash == rom_hash)
+            .map(|listing| listing.entries.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn update_listing(&mut self, chip8_state: &chip8::State) {
+        let memory = &chip8_state.memory[ROM_START as usize..];
+        let mut entries = match self.listing.take() {
+            Some(mut listing) if listing.rom_hash == chip8_state.rom_hash() => {
+                let pc = chip8_state.pc;
+                // roms write to their data all the time, only writes to code need tracing again
+                let unchanged = listing.platform == chip8_state.platform()
+                    && (listing.code == memory || listing.update_data(memory));
+                if unchanged && (pc < ROM_START || listing.disassembly.is_instruction(pc)) {
+                    self.listing = Some(listing);
+                    return;
+                }
+                listing.entries
             }
+            _ => BTreeSet::from([ROM_START]),
+        };
+        if chip8_state.pc >= ROM_START {
+            entries.insert(chip8_state.pc);
         }
-        ui.allocate_space(ui.available_size());
-    });
-}
+        self.listing = Some(Listing::new(chip8_state, entries));
+    }
 
-fn draw_line(ui: &mut egui::Ui, list_pc: usize, highlighted: bool, bytes: &[u8]) {
-    let (instr, args) = {
-        if let Ok(op) = chip8::Op::new(bytes[0], bytes[1]) {
-            op.disassemble()
-        } else {
-            (String::new(), String::new())
+    fn draw_list(
+        &mut self,
+        ui: &mut egui::Ui,
+        chip8_state: &chip8::State,
+        breakpoints: &mut Breakpoints,
+    ) {
+        let Some(listing) = &self.listing else {
+            return;
+        };
+        ui.style_mut()
+            .text_styles
+            .insert(Body, FontId::monospace(11.0));
+        let row_height = ui.text_style_height(&Body);
+        ui.spacing_mut().interact_size.y = row_height;
+        ui.spacing_mut().item_spacing.y = 0.0;
+
+        let mut scroll_area = egui::ScrollArea::vertical();
+        if self.follow_pc && self.followed_pc != Some(chip8_state.pc) {
+            self.followed_pc = Some(chip8_state.pc);
+            if let Some(row) = listing.row(chip8_state.pc) {
+                // a few lines of context above it
+                #[allow(clippy::cast_precision_loss)]
+                let offset = row.saturating_sub(8) as f32 * row_height;
+                scroll_area = scroll_area.vertical_scroll_offset(offset);
+            }
         }
+        scroll_area.show_rows(ui, row_height, listing.lines.len(), |ui, row_range| {
+            for line in &listing.lines[row_range] {
+                ui.horizontal(|ui| match line {
+                    Line::Label { name, .. } if self.octo => {
+                        ui.colored_label(LABEL_COLOR, format!(": {name}"));
+                    }
+                    Line::Label { name, .. } => {
+                        ui.colored_label(LABEL_COLOR, format!("{name}:"));
+                    }
+                    &Line::Instruction {
+                        address,
+                        op,
+                        ref bytes,
+                    } => {
+                        if draw_breakpoint(ui, breakpoints.pc.get(&address)) {
+                            breakpoints.toggle_pc(address);
+                        }
+                        let highlighted = chip8_state.pc == address;
+                        let selected = self.selected == Some(address);
+                        let text = if self.octo {
+                            let statement = listing.disassembly.format_octo(op);
+                            // colored like an instruction up to the first space
+                            match statement.split_once(' ') {
+                                Some((first, rest)) => (format!("{first} "), rest.to_string()),
+                                None => (statement, String::new()),
+                            }
+                        } else {
+                            listing.disassembly.format(op)
+                        };
+                        if draw_line(ui, address, highlighted, selected, bytes, text) {
+                            self.selected = Some(address);
+                        }
+                    }
+                    &Line::Data { address, ref bytes } => draw_data(ui, address, bytes[0]),
+                });
+            }
+            ui.allocate_space(ui.available_size());
+        });
+    }
+}
+
+// A dot in the gutter, returns whether it was clicked to toggle the breakpoint.
+fn draw_breakpoint(ui: &mut egui::Ui, breakpoint: Option<&Option<chip8::Condition>>) -> bool {
+    let dot = match breakpoint {
+        Some(_) => RichText::new("●").color(Color32::LIGHT_RED),
+        None => RichText::new("○").color(Color32::from_rgb(60, 60, 60)),
     };
-    ui.horizontal_wrapped(|ui| {
-        // if both are zeros, draw grayed out text
-        if bytes[0..2] == [0, 0] {
-            ui.visuals_mut().override_text_color = Some(Color32::from_rgb(100, 100, 100));
-        } else if highlighted {
-            ui.visuals_mut().override_text_color = Some(Color32::LIGHT_GRAY);
-        }
+    let response = ui.add(egui::Label::new(dot).sense(Sense::click()));
+    match breakpoint {
+        Some(Some(condition)) => response.on_hover_text(format!("if {condition}")),
+        _ => response,
+    }
+    .clicked()
+}
 
-        ui.label(format!(
-            "{:04x}: {:02x}{:02x} ",
-            list_pc, bytes[0], bytes[1]
-        ));
-        ui.colored_label(Color32::from_rgb(128, 140, 255), instr);
-        ui.label(args);
+// Returns whether the line was clicked.
+fn draw_line(
+    ui: &mut egui::Ui,
+    address: u16,
+    highlighted: bool,
+    selected: bool,
+    bytes: &[u8],
+    (instruction, args): (String, String),
+) -> bool {
+    if highlighted {
+        ui.visuals_mut().override_text_color = Some(Color32::LIGHT_GRAY);
+    }
+    let hex = bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
     });
+    let clicked = ui
+        .selectable_label(selected, format!("{address:04x}: {hex:8} "))
+        .clicked();
+    ui.colored_label(Color32::from_rgb(128, 140, 255), instruction);
+    ui.label(args);
+    clicked
+}
+
+// Bytes that no path reaches, drawn as a row of sprite pixels since that's what they usually are.
+fn draw_data(ui: &mut egui::Ui, address: u16, byte: u8) {
+    ui.visuals_mut().override_text_color = Some(Color32::from_rgb(100, 100, 100));
+    let pixels: String = (0..8)
+        .map(|column| {
+            if byte << column & 0b1000_0000 != 0 {
+                '█'
+            } else {
+                '·'
+            }
+        })
+        .collect();
+    ui.label(format!("  {address:04x}: {byte:02x}       "));
+    ui.label(RichText::new(pixels).color(Color32::LIGHT_GRAY));
 }
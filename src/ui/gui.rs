@@ -1,32 +1,358 @@
+use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
 
 use eframe::epaint::textures::TextureOptions;
-use egui::{ColorImage, Context, TextureHandle};
+use egui::{Color32, ColorImage, Context, InputState, Key, Modifiers, TextureHandle};
 
-use crate::chip8::State;
+use crate::audio::{AudioSettings, WavAudio};
+use crate::chip8::{
+    Debugger, Disassembly, Movie, Platform, Quirks, ROM_START, Rewind, State, compile_octo,
+    rom_hash,
+};
+use crate::gamepad::Button;
 use crate::ui;
-use crate::ui::{Disassembler, Registers};
+use crate::ui::rom_settings::{self, RomSettings};
+use crate::ui::{
+    BreakpointsWindow, Disassembler, KeyBindingsWindow, Keypad, MemoryView, Registers,
+};
+
+// Colors for pixels that are on in no plane, plane 1, plane 2 and both planes.
+const PALETTE: [Color32; 4] = [
+    Color32::BLACK,
+    Color32::WHITE,
+    Color32::from_rgb(0xff, 0x66, 0x00),
+    Color32::from_rgb(0x66, 0x22, 0x00),
+];
+
+// Quick-load with F1-F4, quick-save with shift held.
+pub const SAVE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+// Held to run backwards through the rewind history.
+pub const REWIND_KEY: Key = Key::Backspace;
+const FRAMES_PER_SECOND: usize = 60;
 
 pub struct Gui {
     pub disassembler: Disassembler,
     pub registers: Registers,
+    pub breakpoints: BreakpointsWindow,
+    pub memory: MemoryView,
+    pub key_bindings: KeyBindingsWindow,
+    pub keypad: Keypad,     // On-screen keypad.
+    pub debugger: Debugger, // Pauses and steps the emulation.
+    pub audio: AudioSettings,
+    pub cycles_per_frame: usize, // Instructions emulated every 60 Hz frame.
+    pub recording: Option<WavAudio>, // Audio being recorded to a file.
+    pub rewind: Rewind,          // A state for every frame emulated recently.
+    pub movie: Option<MovieMode>,
+    rewind_seconds: usize,
     screen: Option<TextureHandle>, // Chip8's framebuffer as a texture.
+    rom_settings: HashMap<u64, RomSettings>, // Settings picked for each rom, saved in roms.cfg.
+    error: Option<String>,         // Shown in a window until it's closed.
+    save_slots: [Option<Vec<u8>>; SAVE_SLOT_KEYS.len()], // Quick-save states, for this session only.
+    rom: Option<(String, Vec<u8>)>, // File name and contents of the rom that was opened.
+}
+
+pub enum MovieMode {
+    Recording(Movie),
+    Playing(Movie),
 }
 
 impl Gui {
+    // Loads the settings saved for each rom, if there are any.
     pub fn new() -> Self {
+        let (rom_settings, error) = match rom_settings::load() {
+            Ok(rom_settings) => (rom_settings, None),
+            Err(error) => (HashMap::new(), Some(error)),
+        };
         Self {
             disassembler: Disassembler::new(),
             registers: Registers::new(),
+            breakpoints: BreakpointsWindow::new(),
+            memory: MemoryView::new(),
+            key_bindings: KeyBindingsWindow::new(),
+            keypad: Keypad::new(),
+            debugger: Debugger::new(),
+            audio: AudioSettings::new(),
+            cycles_per_frame: 10,
+            recording: None,
+            rewind: Rewind::new(10 * FRAMES_PER_SECOND),
+            movie: None,
+            rewind_seconds: 10,
             screen: None,
+            rom_settings,
+            error,
+            save_slots: Default::default(),
+            rom: None,
+        }
+    }
+
+    // Loads a rom with the platform and quirks that were last picked for it, if any.
+    pub fn load_rom(&mut self, chip8_state: &mut State, path: &Path) {
+        let rom = if path.extension().is_some_and(|extension| extension == "8o") {
+            match fs::read_to_string(path) {
+                Ok(source) => match compile_octo(&source) {
+                    Ok(rom) => rom,
+                    Err(error) => {
+                        self.show_error(format!("Couldn't compile the program: {error}"));
+                        return;
+                    }
+                },
+                Err(error) => {
+                    self.show_error(format!("Couldn't read the program: {error}"));
+                    return;
+                }
+            }
+        } else {
+            match fs::read(path) {
+                Ok(rom) => rom,
+                Err(error) => {
+                    self.show_error(format!("Couldn't read the rom: {error}"));
+                    return;
+                }
+            }
+        };
+        // the rom's platform can resize memory, so a rom that doesn't fit has that undone
+        let before = chip8_state.save_state();
+        if let Some(settings) = self.rom_settings.get(&rom_hash(&rom)) {
+            chip8_state.set_platform(settings.platform);
+            chip8_state.quirks = settings.quirks;
+        }
+        if let Err(error) = chip8_state.load_rom(&rom) {
+            chip8_state
+                .load_state(&before)
+                .expect("the state was saved by this build");
+            self.show_error(format!("Couldn't load the rom: {error}"));
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            self.rom = Some((name.into_owned(), rom));
+            // a movie only makes sense for the rom it started with
+            self.movie = None;
+            // and rewinding mustn't go back into the previous rom
+            self.rewind.clear();
+            // the new rom starts on a whole frame, even if the old one was paused mid-frame
+            self.debugger.reset_frame();
+            self.debugger.resume();
+        }
+    }
+
+    // Writes the loaded rom as assembly, see Disassembly::export.
+    pub fn export_disassembly(&mut self, chip8_state: &State, path: &Path) {
+        let Some((name, rom)) = &self.rom else {
+            self.show_error(String::from("Open a rom to export first."));
+            return;
+        };
+        // the disassembler knows where the rom was seen jumping to, e.g. with JUMP0
+        let mut entries = self.disassembler.entries(rom_hash(rom));
+        entries.push(ROM_START);
+        let source =
+            Disassembly::new(rom, ROM_START, chip8_state.platform(), &entries).export(name);
+        if let Err(error) = fs::write(path, source) {
+            self.show_error(format!("Couldn't export the disassembly: {error}"));
+        }
+    }
+
+    // The CHIP-8 keys held on the keyboard, a gamepad or the on-screen keypad.
+    pub fn keyboard(
+        &mut self,
+        input: &InputState,
+        buttons: &[Button],
+        rom_hash: u64,
+    ) -> [bool; 16] {
+        let keyboard = self.key_bindings.keyboard(input, buttons, rom_hash);
+        std::array::from_fn(|key| keyboard[key] || self.keypad.held[key])
+    }
+
+    pub fn show_error(&mut self, message: String) {
+        self.error = Some(message);
+    }
+
+    // Changes the platform and quirks and saves them for the loaded rom.
+    pub fn set_platform(&mut self, chip8_state: &mut State, platform: Platform, quirks: Quirks) {
+        chip8_state.set_platform(platform);
+        chip8_state.quirks = quirks;
+        if chip8_state.rom_loaded {
+            self.rom_settings
+                .insert(chip8_state.rom_hash(), RomSettings { platform, quirks });
+            if let Err(error) = rom_settings::save(&self.rom_settings) {
+                self.show_error(error);
+            }
+        }
+    }
+
+    pub fn rewind_seconds(&self) -> usize {
+        self.rewind_seconds
+    }
+
+    pub fn set_rewind_seconds(&mut self, seconds: usize) {
+        self.rewind_seconds = seconds;
+        self.rewind.set_capacity(seconds * FRAMES_PER_SECOND);
+    }
+
+    pub fn quick_save(&mut self, chip8_state: &State, slot: usize) {
+        self.save_slots[slot] = Some(chip8_state.save_state());
+    }
+
+    pub fn quick_load(&mut self, chip8_state: &mut State, slot: usize) {
+        if let Some(save_state) = &self.save_slots[slot] {
+            // can't fail, the slot was saved by this build
+            chip8_state.load_state(save_state).unwrap();
+        }
+    }
+
+    pub fn slot_saved(&self, slot: usize) -> bool {
+        self.save_slots[slot].is_some()
+    }
+
+    pub fn save_state_file(&mut self, chip8_state: &State, path: &Path) {
+        if let Err(error) = fs::write(path, chip8_state.save_state()) {
+            self.show_error(format!("Couldn't save the state: {error}"));
+        }
+    }
+
+    pub fn load_state_file(&mut self, chip8_state: &mut State, path: &Path) {
+        let result = fs::read(path)
+            .map_err(|error| error.to_string())
+            .and_then(|data| {
+                chip8_state
+                    .load_state(&data)
+                    .map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            self.show_error(format!("Couldn't load the state: {error}"));
+        }
+    }
+
+    pub fn start_recording(&mut self, path: &Path) {
+        match WavAudio::create(path) {
+            Ok(recording) => self.recording = Some(recording),
+            Err(error) => self.show_error(format!("Couldn't start recording: {error}")),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take()
+            && let Err(error) = recording.finish()
+        {
+            self.show_error(format!("Couldn't save the recording: {error}"));
+        }
+    }
+
+    // Restarts the rom and records the keys held on every frame from then on.
+    pub fn record_movie(&mut self, chip8_state: &mut State) {
+        let Some((_, rom)) = &self.rom else {
+            self.show_error(String::from("Open a rom to record a movie of first."));
+            return;
+        };
+        match Movie::record(chip8_state, rom, self.cycles_per_frame) {
+            Ok(movie) => {
+                // states from before the restart would throw the frame count off
+                self.rewind.clear();
+                self.debugger.reset_frame();
+                self.movie = Some(MovieMode::Recording(movie));
+            }
+            Err(error) => self.show_error(format!("Couldn't restart the rom: {error}")),
+        }
+    }
+
+    // Stops recording and writes the movie.
+    pub fn save_movie(&mut self, path: &Path) {
+        if let Some(MovieMode::Recording(movie)) = self.movie.take()
+            && let Err(error) = fs::write(path, movie.save())
+        {
+            self.show_error(format!("Couldn't save the movie: {error}"));
+        }
+    }
+
+    // Restarts the loaded rom the way the movie was recorded, and plays back its keys.
+    pub fn play_movie(&mut self, chip8_state: &mut State, path: &Path) {
+        let Some((_, rom)) = &self.rom else {
+            self.show_error(String::from(
+                "Open the rom the movie was recorded with first.",
+            ));
+            return;
+        };
+        let result = fs::read(path)
+            .map_err(|error| error.to_string())
+            .and_then(|data| Movie::load(&data).map_err(|error| error.to_string()))
+            .and_then(|movie| {
+                movie
+                    .start(chip8_state, rom)
+                    .map_err(|error| error.to_string())?;
+                Ok(movie)
+            });
+        match result {
+            Ok(movie) => {
+                self.cycles_per_frame = movie.cycles_per_frame;
+                self.rewind.clear();
+                self.debugger.reset_frame();
+                self.movie = Some(MovieMode::Playing(movie));
+            }
+            Err(error) => self.show_error(format!("Couldn't play the movie: {error}")),
+        }
+    }
+
+    // Records or plays back the keys for the frame that's about to run.
+    pub fn movie_frame(&mut self, chip8_state: &mut State) {
+        match &mut self.movie {
+            Some(MovieMode::Recording(movie)) => {
+                self.cycles_per_frame = movie.cycles_per_frame;
+                movie.record_frame(chip8_state);
+            }
+            Some(MovieMode::Playing(movie)) => {
+                self.cycles_per_frame = movie.cycles_per_frame;
+                if !movie.play_frame(chip8_state) {
+                    self.movie = None;
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn handle_hotkeys(&mut self, ctx: &Context, chip8_state: &mut State) {
+        for (slot, key) in SAVE_SLOT_KEYS.into_iter().enumerate() {
+            if ctx.input_mut(|i| i.consume_key(Modifiers::SHIFT, key)) {
+                self.quick_save(chip8_state, slot);
+            }
+            if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, key)) {
+                self.quick_load(chip8_state, slot);
+            }
         }
     }
 
     pub fn ui(&mut self, ctx: &Context, chip8_state: &mut State) {
+        self.handle_hotkeys(ctx, chip8_state);
         ui::top_bar::draw(ctx, self, chip8_state);
-        self.disassembler.draw(ctx, chip8_state);
-        self.registers.draw(ctx, chip8_state);
+        ui::toolbar::draw(ctx, self, chip8_state);
+        self.disassembler
+            .draw(ctx, chip8_state, &mut self.debugger.breakpoints);
+        self.registers
+            .draw(ctx, chip8_state, self.debugger.paused());
+        self.breakpoints.draw(ctx, &mut self.debugger.breakpoints);
+        self.memory.draw(ctx, chip8_state, self.debugger.paused());
+        let rom = self
+            .rom
+            .as_ref()
+            .map(|(name, _)| (name.as_str(), chip8_state.rom_hash()));
+        self.key_bindings.draw(ctx, rom, chip8_state.keyboard);
+        self.keypad.draw(ctx, chip8_state.keyboard);
+        self.draw_error(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(halt) = chip8_state.halted() {
+                ui.colored_label(Color32::LIGHT_RED, format!("Halted: {halt}"));
+            }
+            match &self.movie {
+                Some(MovieMode::Recording(_)) => {
+                    ui.label(format!("Recording movie, frame {}", chip8_state.frame()));
+                }
+                Some(MovieMode::Playing(movie)) => {
+                    let length = movie.frames.len();
+                    ui.label(format!(
+                        "Playing movie, frame {} of {length}",
+                        chip8_state.frame()
+                    ));
+                }
+                None => {}
+            }
             let frame = self.screen.get_or_insert_with(|| {
                 ctx.load_texture(
                     "Chip8 Screen",
@@ -35,10 +361,16 @@ impl Gui {
                 )
             });
 
-            frame.set(
-                ColorImage::from_gray([64, 32], &chip8_state.frame_grayscale()),
-                TextureOptions::NEAREST,
-            );
+            let display = &mut chip8_state.display;
+            if display.take_dirty() {
+                let size = [display.width(), display.height()];
+                let pixels = display
+                    .frame()
+                    .into_iter()
+                    .map(|pixel| PALETTE[pixel as usize])
+                    .collect();
+                frame.set(ColorImage { size, pixels }, TextureOptions::NEAREST);
+            }
             ui.add(
                 egui::Image::new(&*frame)
                     .maintain_aspect_ratio(true)
@@ -51,9 +383,22 @@ impl Gui {
             if !i.raw.dropped_files.is_empty() {
                 let dropped_file = i.raw.dropped_files.first().unwrap().clone();
                 let path = dropped_file.path.unwrap();
-                let rom = fs::read(path).expect("failed to read file");
-                chip8_state.load_rom(&rom);
+                self.load_rom(chip8_state, &path);
             }
         });
     }
+
+    fn draw_error(&mut self, ctx: &Context) {
+        let mut open = self.error.is_some();
+        if let Some(error) = &self.error {
+            egui::Window::new("Error")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| ui.label(error));
+        }
+        if !open {
+            self.error = None;
+        }
+    }
 }
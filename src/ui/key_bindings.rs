@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+
+use egui::{Color32, Event, InputState, Key, RichText};
+
+use crate::gamepad::{Button, GamepadProfile};
+use crate::ui::{KEYPAD_LAYOUT, config_path};
+
+const FILE_NAME: &str = "keys.cfg";
+
+// Host keys in the same 4x4 block as KEYPAD_LAYOUT, for a few keyboard layouts.
+const PRESETS: [(&str, [[Key; 4]; 4]); 3] = [
+    (
+        "QWERTY",
+        [
+            [Key::Num1, Key::Num2, Key::Num3, Key::Num4],
+            [Key::Q, Key::W, Key::E, Key::R],
+            [Key::A, Key::S, Key::D, Key::F],
+            [Key::Z, Key::X, Key::C, Key::V],
+        ],
+    ),
+    (
+        "AZERTY",
+        [
+            [Key::Num1, Key::Num2, Key::Num3, Key::Num4],
+            [Key::A, Key::Z, Key::E, Key::R],
+            [Key::Q, Key::S, Key::D, Key::F],
+            [Key::W, Key::X, Key::C, Key::V],
+        ],
+    ),
+    (
+        "Dvorak",
+        [
+            [Key::Num1, Key::Num2, Key::Num3, Key::Num4],
+            [Key::Quote, Key::Comma, Key::Period, Key::P],
+            [Key::A, Key::O, Key::E, Key::U],
+            [Key::Semicolon, Key::Q, Key::J, Key::K],
+        ],
+    ),
+];
+
+// The host keys and gamepad buttons bound to each CHIP-8 key.
+#[derive(Clone, PartialEq, Eq)]
+struct KeyMap {
+    keys: [Vec<Key>; 16],
+    gamepad: GamepadProfile,
+}
+
+impl KeyMap {
+    fn empty() -> Self {
+        KeyMap {
+            keys: [const { Vec::new() }; 16],
+            gamepad: GamepadProfile::empty(),
+        }
+    }
+
+    fn preset_keys(rows: [[Key; 4]; 4]) -> [Vec<Key>; 16] {
+        let mut keys: [Vec<Key>; 16] = Default::default();
+        for (layout_row, row) in KEYPAD_LAYOUT.iter().zip(rows) {
+            for (&chip8_key, key) in layout_row.iter().zip(row) {
+                keys[chip8_key] = vec![key];
+            }
+        }
+        keys
+    }
+
+    fn bind(&mut self, chip8_key: usize, key: Key) {
+        // a host key only presses one CHIP-8 key
+        for keys in &mut self.keys {
+            keys.retain(|&bound| bound != key);
+        }
+        self.keys[chip8_key].push(key);
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            keys: KeyMap::preset_keys(PRESETS[0].1),
+            gamepad: GamepadProfile::default(),
+        }
+    }
+}
+
+pub struct KeyBindingsWindow {
+    pub open: bool,
+    bindings: KeyMap,                   // For every rom without its own.
+    rom_bindings: HashMap<u64, KeyMap>, // Bindings for particular roms, by rom hash.
+    selected: usize,                    // CHIP-8 key being edited.
+    capturing: bool,                    // Waiting for a key or button to bind to the selected one.
+    captured_button: Option<Button>,    // Gamepad button pressed while capturing.
+    buttons_held: Vec<Button>,          // Gamepad buttons held last frame.
+    error: Option<String>,              // Why the config file couldn't be read or written.
+}
+
+impl KeyBindingsWindow {
+    // Loads the bindings saved in the config file, if there are any.
+    pub fn new() -> Self {
+        let mut window = Self {
+            open: false,
+            bindings: KeyMap::default(),
+            rom_bindings: HashMap::new(),
+            selected: 0,
+            capturing: false,
+            captured_button: None,
+            buttons_held: Vec::new(),
+            error: None,
+        };
+        if let Some(path) = config_path(FILE_NAME)
+            && let Ok(config) = fs::read_to_string(&path)
+            && let Err(error) = window.parse(&config)
+        {
+            window.error = Some(format!("{}: {error}", path.display()));
+        }
+        window
+    }
+
+    // Which CHIP-8 keys are held on the keyboard or a gamepad, with the bindings for the rom.
+    pub fn keyboard(
+        &mut self,
+        input: &InputState,
+        buttons: &[Button],
+        rom_hash: u64,
+    ) -> [bool; 16] {
+        let pressed = buttons
+            .iter()
+            .find(|button| !self.buttons_held.contains(button));
+        if self.capturing {
+            // the key or button being bound isn't a key press
+            self.captured_button = pressed.copied();
+            self.buttons_held = buttons.to_vec();
+            return [false; 16];
+        }
+        self.buttons_held = buttons.to_vec();
+        let bindings = self.rom_bindings.get(&rom_hash).unwrap_or(&self.bindings);
+        let gamepad = bindings.gamepad.keyboard(buttons);
+        std::array::from_fn(|chip8_key| {
+            gamepad[chip8_key]
+                || bindings.keys[chip8_key]
+                    .iter()
+                    .any(|&key| input.key_down(key))
+        })
+    }
+
+    // rom is the loaded rom's file name and hash, keyboard is which keys are held.
+    pub fn draw(&mut self, ctx: &egui::Context, rom: Option<(&str, u64)>, keyboard: [bool; 16]) {
+        let mut open = self.open;
+        egui::Window::new("Key bindings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut changed = false;
+                if let Some((name, hash)) = rom {
+                    let mut own_bindings = self.rom_bindings.contains_key(&hash);
+                    if ui
+                        .checkbox(&mut own_bindings, format!("Only for {name}"))
+                        .changed()
+                    {
+                        if own_bindings {
+                            self.rom_bindings.insert(hash, self.bindings.clone());
+                        } else {
+                            self.rom_bindings.remove(&hash);
+                        }
+                        changed = true;
+                    }
+                }
+                let bindings = match rom {
+                    Some((_, hash)) => self
+                        .rom_bindings
+                        .get_mut(&hash)
+                        .unwrap_or(&mut self.bindings),
+                    None => &mut self.bindings,
+                };
+                ui.separator();
+                draw_keypad(ui, bindings, keyboard, &mut self.selected);
+                ui.separator();
+
+                let selected = self.selected;
+                ui.strong(format!("Key {selected:X}"));
+                ui.horizontal_wrapped(|ui| {
+                    changed |= draw_bound(ui, bindings, selected);
+                    if self.capturing {
+                        ui.label("Press a key or gamepad button, or Escape to cancel...");
+                    } else if ui.button("Add...").clicked() {
+                        self.capturing = true;
+                        self.captured_button = None;
+                    }
+                });
+                if self.capturing
+                    && let Some(key) = ctx.input(pressed_key)
+                {
+                    if key != Key::Escape {
+                        bindings.bind(selected, key);
+                        changed = true;
+                    }
+                    self.capturing = false;
+                }
+                if self.capturing
+                    && let Some(button) = self.captured_button.take()
+                {
+                    bindings.gamepad.bind(selected, button);
+                    changed = true;
+                    self.capturing = false;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Reset keys to");
+                    for (name, rows) in PRESETS {
+                        if ui.button(name).clicked() {
+                            bindings.keys = KeyMap::preset_keys(rows);
+                            changed = true;
+                        }
+                    }
+                });
+                if ui.button("Reset gamepad").clicked() {
+                    bindings.gamepad = GamepadProfile::default();
+                    changed = true;
+                }
+                if changed {
+                    self.save();
+                }
+                if let Some(error) = &self.error {
+                    ui.separator();
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+            });
+        if !open {
+            self.capturing = false;
+        }
+        self.open = open;
+    }
+
+    fn save(&mut self) {
+        let Some(path) = config_path(FILE_NAME) else {
+            self.error = Some(String::from(
+                "There's nowhere to save the bindings, HOME isn't set.",
+            ));
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, self.to_config()));
+        self.error = result
+            .err()
+            .map(|error| format!("Couldn't save {}: {error}", path.display()));
+    }
+
+    // Lines of a CHIP-8 key then the keys bound to it, with a [rom <hash>] line before the
+    // bindings for each rom that has its own.
+    fn to_config(&self) -> String {
+        let mut config =
+            String::from("# CHIP-8 key, then the keys and gamepad buttons that press it\n");
+        write_bindings(&mut config, &self.bindings);
+        let mut hashes: Vec<_> = self.rom_bindings.keys().copied().collect();
+        hashes.sort_unstable();
+        for hash in hashes {
+            let _ = writeln!(config, "\n[rom {hash:016x}]");
+            write_bindings(&mut config, &self.rom_bindings[&hash]);
+        }
+        config
+    }
+
+    fn parse(&mut self, config: &str) -> Result<(), String> {
+        let mut bindings = KeyMap::empty();
+        let mut rom_bindings = HashMap::new();
+        let mut rom = None; // Hash of the rom the lines are for, None for every rom.
+        for (number, line) in (1..).zip(config.lines()) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {number}: {message}");
+            if let Some(hash) = line
+                .strip_prefix("[rom ")
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                let hash = u64::from_str_radix(hash.trim(), 16)
+                    .map_err(|_| error(format!("\"{hash}\" isn't a rom hash")))?;
+                rom = Some(hash);
+                rom_bindings.insert(hash, KeyMap::empty());
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let chip8_key = words
+                .next()
+                .and_then(|word| usize::from_str_radix(word, 16).ok())
+                .filter(|&chip8_key| chip8_key < 16)
+                .ok_or_else(|| error(String::from("expected a CHIP-8 key, 0 to F")))?;
+            let map = match rom {
+                Some(hash) => rom_bindings.get_mut(&hash).unwrap(),
+                None => &mut bindings,
+            };
+            for word in words {
+                if let Some(key) = Key::from_name(word) {
+                    map.bind(chip8_key, key);
+                } else if let Some(button) = Button::from_name(word) {
+                    map.gamepad.bind(chip8_key, button);
+                } else {
+                    return Err(error(format!("unknown key \"{word}\"")));
+                }
+            }
+        }
+        self.bindings = bindings;
+        self.rom_bindings = rom_bindings;
+        Ok(())
+    }
+}
+
+fn write_bindings(config: &mut String, bindings: &KeyMap) {
+    for (chip8_key, keys) in bindings.keys.iter().enumerate() {
+        let _ = write!(config, "{chip8_key:X}");
+        for key in keys {
+            let _ = write!(config, " {}", key.name());
+        }
+        for button in &bindings.gamepad.buttons[chip8_key] {
+            let _ = write!(config, " {}", button.name());
+        }
+        config.push('\n');
+    }
+}
+
+// The keys and buttons bound to chip8_key, returns whether one was clicked to unbind it.
+fn draw_bound(ui: &mut egui::Ui, bindings: &mut KeyMap, chip8_key: usize) -> bool {
+    // every button is drawn, even after one's been clicked
+    let keys = &mut bindings.keys[chip8_key];
+    let removed_keys: Vec<Key> = keys
+        .iter()
+        .copied()
+        .filter(|key| unbind_button(ui, key.name()))
+        .collect();
+    keys.retain(|key| !removed_keys.contains(key));
+    let buttons = &mut bindings.gamepad.buttons[chip8_key];
+    let removed_buttons: Vec<Button> = buttons
+        .iter()
+        .copied()
+        .filter(|button| unbind_button(ui, button.name()))
+        .collect();
+    buttons.retain(|button| !removed_buttons.contains(button));
+    !removed_keys.is_empty() || !removed_buttons.is_empty()
+}
+
+// Returns whether it was clicked to unbind the key or button.
+fn unbind_button(ui: &mut egui::Ui, name: &str) -> bool {
+    ui.button(format!("{name} ✖"))
+        .on_hover_text("Unbind")
+        .clicked()
+}
+
+// The first key pressed this frame.
+fn pressed_key(input: &InputState) -> Option<Key> {
+    input.events.iter().find_map(|event| match *event {
+        Event::Key {
+            key,
+            pressed: true,
+            repeat: false,
+            ..
+        } => Some(key),
+        _ => None,
+    })
+}
+
+// The keypad with what each key is bound to, held keys lit up. Clicking a key selects it.
+fn draw_keypad(ui: &mut egui::Ui, bindings: &KeyMap, keyboard: [bool; 16], selected: &mut usize) {
+    egui::Grid::new("key bindings")
+        .spacing([4.0, 4.0])
+        .show(ui, |ui| {
+            for row in KEYPAD_LAYOUT {
+                for chip8_key in row {
+                    let keys = bindings.keys[chip8_key].iter().map(|key| key.name());
+                    let buttons = bindings.gamepad.buttons[chip8_key]
+                        .iter()
+                        .map(|button| button.name());
+                    let names: Vec<&str> = keys.chain(buttons).collect();
+                    let mut text =
+                        RichText::new(format!("{chip8_key:X}\n{}", names.join(" "))).monospace();
+                    if keyboard[chip8_key] {
+                        text = text.strong();
+                    }
+                    let button = egui::Button::new(text)
+                        .selected(*selected == chip8_key)
+                        .min_size(egui::vec2(64.0, 40.0));
+                    if ui.add(button).clicked() {
+                        *selected = chip8_key;
+                    }
+                }
+                ui.end_row();
+            }
+        });
+}
@@ -0,0 +1,46 @@
+use egui::{RichText, Sense};
+
+use crate::ui::KEYPAD_LAYOUT;
+
+// A hex keypad that's played by holding its keys down with the mouse, or a finger.
+pub struct Keypad {
+    pub open: bool,
+    pub held: [bool; 16], // Keys held down on the keypad.
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            held: [false; 16],
+        }
+    }
+
+    // keyboard is what the rom sees held, from any input, and gets lit up.
+    pub fn draw(&mut self, ctx: &egui::Context, keyboard: [bool; 16]) {
+        let mut open = self.open;
+        self.held = [false; 16];
+        egui::Window::new("Keypad")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("keypad")
+                    .spacing([4.0, 4.0])
+                    .show(ui, |ui| {
+                        for row in KEYPAD_LAYOUT {
+                            for key in row {
+                                let text = RichText::new(format!("{key:X}")).monospace().size(20.0);
+                                let button = egui::Button::new(text)
+                                    .selected(keyboard[key])
+                                    .min_size(egui::vec2(48.0, 48.0))
+                                    .sense(Sense::click_and_drag());
+                                // held for as long as the press that started on it lasts
+                                self.held[key] = ui.add(button).is_pointer_button_down_on();
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        self.open = open;
+    }
+}
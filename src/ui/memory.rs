@@ -0,0 +1,251 @@
+use egui::TextStyle::Body;
+use egui::{Color32, FontId, RichText, Sense, Ui};
+
+use crate::chip8::State;
+
+const BYTES_PER_ROW: usize = 16;
+const FLASH_FRAMES: u8 = 30; // How long changed bytes stay lit.
+const I_COLOR: Color32 = Color32::from_rgb(0x60, 0xc0, 0xff);
+const PC_COLOR: Color32 = Color32::from_rgb(0x80, 0xff, 0x80);
+const RETURN_COLOR: Color32 = Color32::from_rgb(0xff, 0x80, 0xff);
+const FLASH_COLOR: Color32 = Color32::from_rgb(0xff, 0xd0, 0x40);
+
+// Parts of memory with a fixed purpose, start to end (exclusive).
+const REGIONS: [(usize, usize, &str); 4] = [
+    (0x000, 0x050, "font"),
+    (0x050, 0x0f0, "big font"),
+    (0x0f0, 0x200, "reserved"),
+    (0x200, usize::MAX, "program"),
+];
+
+pub struct MemoryView {
+    pub open: bool,
+    go_to: String,
+    scroll_to: Option<usize>, // Row to scroll to on the next draw.
+    selected: Option<usize>,  // Byte that was clicked, edited below the dump.
+    previous: Vec<u8>,        // Memory as it was last drawn.
+    flash: Vec<u8>,           // Frames left to highlight each byte since it changed.
+}
+
+impl MemoryView {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            go_to: String::new(),
+            scroll_to: None,
+            selected: None,
+            previous: Vec::new(),
+            flash: Vec::new(),
+        }
+    }
+
+    // Bytes can be edited while the cpu is paused.
+    pub fn draw(&mut self, ctx: &egui::Context, chip8_state: &mut State, editable: bool) {
+        if !self.open {
+            return;
+        }
+        self.track_changes(&chip8_state.memory);
+
+        let mut open = self.open;
+        egui::Window::new("Memory")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.draw_controls(ui, chip8_state, editable);
+                ui.separator();
+                self.draw_dump(ui, chip8_state);
+            });
+        self.open = open;
+    }
+
+    fn track_changes(&mut self, memory: &[u8]) {
+        if self.previous.len() != memory.len() {
+            self.previous = memory.to_vec();
+            self.flash = vec![0; memory.len()];
+        }
+        for ((old, &new), flash) in self.previous.iter_mut().zip(memory).zip(&mut self.flash) {
+            if *old == new {
+                *flash = flash.saturating_sub(1);
+            } else {
+                *old = new;
+                *flash = FLASH_FRAMES;
+            }
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &mut Ui, chip8_state: &mut State, editable: bool) {
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.go_to)
+                    .hint_text("Go to address")
+                    .desired_width(100.0),
+            );
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                let text = self.go_to.trim();
+                let hex = text
+                    .strip_prefix("0x")
+                    .or(text.strip_prefix('$'))
+                    .unwrap_or(text);
+                if let Ok(address) = usize::from_str_radix(hex, 16) {
+                    let address = address.min(chip8_state.memory.len() - 1);
+                    self.scroll_to = Some(address / BYTES_PER_ROW);
+                    self.selected = Some(address);
+                }
+            }
+            for (label, address) in [
+                ("I", chip8_state.i() as usize),
+                ("PC", chip8_state.pc as usize),
+            ] {
+                if ui.button(label).clicked() {
+                    self.scroll_to = Some(address / BYTES_PER_ROW);
+                }
+            }
+
+            ui.separator();
+            ui.colored_label(I_COLOR, "I");
+            ui.colored_label(PC_COLOR, "PC");
+            ui.colored_label(RETURN_COLOR, "return address");
+            ui.colored_label(FLASH_COLOR, "changed");
+        });
+
+        let Some(address) = self
+            .selected
+            .filter(|&address| address < chip8_state.memory.len())
+        else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.monospace(format!("${address:04x} in {}:", region(address)));
+            let mut byte = chip8_state.memory[address];
+            let response = ui.add_enabled(
+                editable,
+                egui::DragValue::new(&mut byte)
+                    .hexadecimal(2, false, true)
+                    .prefix("0x"),
+            );
+            if response.changed() {
+                chip8_state.poke(address, byte);
+            }
+            if !editable {
+                ui.weak("pause to edit");
+            }
+        });
+    }
+
+    fn draw_dump(&mut self, ui: &mut Ui, chip8_state: &State) {
+        ui.style_mut()
+            .text_styles
+            .insert(Body, FontId::monospace(11.0));
+        ui.spacing_mut().item_spacing = egui::vec2(4.0, 0.0);
+        let row_height = ui.text_style_height(&Body);
+        let rows = chip8_state.memory.len().div_ceil(BYTES_PER_ROW);
+
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink(false);
+        if let Some(row) = self.scroll_to.take() {
+            #[allow(clippy::cast_precision_loss)]
+            let offset = row as f32 * (row_height + ui.spacing().item_spacing.y);
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+        scroll_area.show_rows(ui, row_height, rows, |ui, row_range| {
+            for row in row_range {
+                self.draw_row(ui, chip8_state, row * BYTES_PER_ROW);
+            }
+        });
+    }
+
+    fn draw_row(&mut self, ui: &mut Ui, chip8_state: &State, start: usize) {
+        let memory = &chip8_state.memory;
+        let end = (start + BYTES_PER_ROW).min(memory.len());
+        let i = chip8_state.i() as usize;
+        let pc = chip8_state.pc as usize;
+        let return_address = chip8_state.stack().last().map(|&address| address as usize);
+
+        ui.horizontal(|ui| {
+            let region_start = REGIONS
+                .iter()
+                .any(|&(region_start, ..)| region_start == start);
+            let address = RichText::new(format!("{start:04x}"));
+            if region_start {
+                ui.label(address.strong())
+                    .on_hover_text(format!("start of {}", region(start)));
+            } else {
+                ui.weak(address);
+            }
+
+            for address in start..end {
+                let byte = memory[address];
+                let mut text = RichText::new(format!("{byte:02x}"));
+                if address == pc || address == pc + 1 {
+                    text = text.background_color(PC_COLOR).color(Color32::BLACK);
+                } else if address == i {
+                    text = text.background_color(I_COLOR).color(Color32::BLACK);
+                } else if return_address == Some(address) {
+                    text = text.background_color(RETURN_COLOR).color(Color32::BLACK);
+                } else if self.flash[address] > 0 {
+                    text =
+                        text.color(FLASH_COLOR.gamma_multiply(
+                            f32::from(self.flash[address]) / f32::from(FLASH_FRAMES),
+                        ));
+                } else if byte == 0 {
+                    text = text.weak();
+                }
+                if self.selected == Some(address) {
+                    text = text.underline();
+                }
+                let response = ui
+                    .add(egui::Label::new(text).sense(Sense::click()))
+                    .on_hover_ui(|ui| draw_sprite_preview(ui, memory, address));
+                if response.clicked() {
+                    self.selected = Some(address);
+                }
+            }
+
+            ui.separator();
+            let ascii: String = memory[start..end]
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            ui.label(ascii);
+            if region_start {
+                ui.weak(format!("← {}", region(start)));
+            }
+        });
+    }
+}
+
+fn region(address: usize) -> &'static str {
+    REGIONS
+        .iter()
+        .find(|&&(start, end, _)| (start..end).contains(&address))
+        .map_or("memory", |&(_, _, name)| name)
+}
+
+// The bytes from address on drawn as an 8 pixel wide sprite, like SPRITE would with I there.
+fn draw_sprite_preview(ui: &mut Ui, memory: &[u8], address: usize) {
+    const ROWS: u8 = 15;
+    const PIXEL: f32 = 6.0;
+    ui.label(format!("${address:04x} as a sprite"));
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(8.0 * PIXEL, f32::from(ROWS) * PIXEL),
+        Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::BLACK);
+    for (row, &byte) in memory.iter().skip(address).take(ROWS.into()).enumerate() {
+        for column in 0..8 {
+            if byte << column & 0b1000_0000 != 0 {
+                #[allow(clippy::cast_precision_loss)]
+                let min = rect.min + egui::vec2(column as f32, row as f32) * PIXEL;
+                let pixel = egui::Rect::from_min_size(min, egui::vec2(PIXEL, PIXEL));
+                painter.rect_filled(pixel, 0.0, Color32::WHITE);
+            }
+        }
+    }
+}
@@ -1,8 +1,38 @@
+mod breakpoints;
 mod disassembler;
+mod key_bindings;
+mod keypad;
+mod memory;
+mod toolbar;
 mod top_bar;
 
 pub mod gui;
 mod registers;
+mod rom_settings;
 
+use std::env;
+use std::path::PathBuf;
+
+use self::breakpoints::BreakpointsWindow;
 use self::disassembler::Disassembler;
+use self::key_bindings::KeyBindingsWindow;
+use self::keypad::Keypad;
+use self::memory::MemoryView;
 use self::registers::Registers;
+
+// The COSMAC VIP's hex keypad, row by row.
+pub const KEYPAD_LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+// A file in $XDG_CONFIG_HOME/chip8uwu, or the same under ~/.config, or %APPDATA% on Windows.
+fn config_path(file_name: &str) -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("chip8uwu").join(file_name))
+}
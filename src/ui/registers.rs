@@ -1,34 +1,181 @@
 use egui::TextStyle::Body;
-use egui::{FontId, Ui};
+use egui::{Color32, FontId, Ui};
 use egui_extras::{Column, TableBody, TableBuilder, TableRow};
 
-use crate::chip8::State;
+use crate::chip8::{Op, State};
+use crate::ui::KEYPAD_LAYOUT;
+
+const CHANGED_COLOR: Color32 = Color32::from_rgb(0xff, 0xd0, 0x40);
 
 pub struct Registers {
     pub open: bool,
+    poke_address: u16,      // The memory byte shown under the registers.
+    shown: Option<Values>,  // What was drawn last.
+    before: Option<Values>, // What was drawn before the values last changed, to highlight changes.
+}
+
+// Everything the panel shows, to compare with what it showed before.
+#[derive(Clone, PartialEq, Eq)]
+struct Values {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    sp: u8,
+    delay: u8,
+    sound: u8,
+    stack: Vec<u16>,
+}
+
+impl Values {
+    fn new(chip8_state: &State) -> Self {
+        Values {
+            v: chip8_state.v,
+            i: chip8_state.i(),
+            pc: chip8_state.pc,
+            sp: chip8_state.sp(),
+            delay: chip8_state.delay,
+            sound: chip8_state.sound(),
+            stack: chip8_state.stack().to_vec(),
+        }
+    }
+}
+
+// A row of the table.
+#[derive(Clone, Copy)]
+enum Field {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+}
+
+impl Field {
+    fn all() -> impl Iterator<Item = Field> {
+        (0..16)
+            .map(Field::V)
+            .chain([Field::I, Field::Pc, Field::Sp, Field::Delay, Field::Sound])
+    }
+
+    fn name(self) -> String {
+        match self {
+            Field::V(v) => format!("V{v:01X}"),
+            Field::I => "I".to_string(),
+            Field::Pc => "PC".to_string(),
+            Field::Sp => "SP".to_string(),
+            Field::Delay => "DT".to_string(),
+            Field::Sound => "ST".to_string(),
+        }
+    }
+
+    fn value(self, values: &Values) -> u16 {
+        match self {
+            Field::V(v) => values.v[v].into(),
+            Field::I => values.i,
+            Field::Pc => values.pc,
+            Field::Sp => values.sp.into(),
+            Field::Delay => values.delay.into(),
+            Field::Sound => values.sound.into(),
+        }
+    }
+
+    fn max(self) -> u16 {
+        match self {
+            Field::I | Field::Pc => u16::MAX,
+            _ => u8::MAX.into(),
+        }
+    }
+
+    // Changes the register through State's setters. SP can't be edited, the stack would stop making sense.
+    fn set(self, chip8_state: &mut State, value: u16) {
+        let byte = u8::try_from(value).unwrap_or(u8::MAX);
+        match self {
+            Field::V(v) => chip8_state.v[v] = byte,
+            Field::I => chip8_state.set_i(value),
+            Field::Pc => chip8_state.set_pc(value),
+            Field::Sp => {}
+            Field::Delay => chip8_state.delay = byte,
+            Field::Sound => chip8_state.set_sound(byte),
+        }
+    }
 }
 
 impl Registers {
     pub fn new() -> Self {
-        Self { open: false }
+        Self {
+            open: true,
+            poke_address: 0x200,
+            shown: None,
+            before: None,
+        }
     }
 
-    pub fn draw(&mut self, ctx: &egui::Context, chip8_state: &State) {
+    // Values can be edited while the cpu is paused.
+    pub fn draw(&mut self, ctx: &egui::Context, chip8_state: &mut State, editable: bool) {
+        if !self.open {
+            return;
+        }
+        let values = Values::new(chip8_state);
+        if self.shown.as_ref() != Some(&values) {
+            self.before = self.shown.replace(values.clone());
+        }
         egui::SidePanel::left("Registers")
             .resizable(false)
-            .show(ctx, |ui| draw_table(ui, chip8_state));
+            .show(ctx, |ui| {
+                draw_table(ui, chip8_state, &values, self.before.as_ref(), editable);
+                ui.separator();
+                self.draw_memory_byte(ui, chip8_state, editable);
+                ui.separator();
+                draw_stack(ui, chip8_state, &values, self.before.as_ref());
+                ui.separator();
+                draw_keypad(ui, chip8_state);
+            });
+    }
+
+    fn draw_memory_byte(&mut self, ui: &mut Ui, chip8_state: &mut State, editable: bool) {
+        ui.horizontal(|ui| {
+            ui.strong("Memory");
+            let max = u16::try_from(chip8_state.memory.len() - 1).unwrap_or(u16::MAX);
+            self.poke_address = self.poke_address.min(max); // memory shrinks with the platform
+            ui.add(
+                egui::DragValue::new(&mut self.poke_address)
+                    .range(0..=max)
+                    .hexadecimal(3, false, false)
+                    .prefix("$"),
+            );
+            let address = self.poke_address as usize;
+            let mut byte = chip8_state.memory[address];
+            let response = ui.add_enabled(
+                editable,
+                egui::DragValue::new(&mut byte)
+                    .hexadecimal(2, false, true)
+                    .prefix("0x"),
+            );
+            if response.changed() {
+                chip8_state.poke(address, byte);
+            }
+        });
     }
 }
 
-fn draw_table(ui: &mut Ui, chip8_state: &State) {
+fn draw_table(
+    ui: &mut Ui,
+    chip8_state: &mut State,
+    values: &Values,
+    before: Option<&Values>,
+    editable: bool,
+) {
     ui.vertical(|ui| {
         TableBuilder::new(ui)
+            .id_salt("registers")
             .striped(true)
+            .vscroll(false)
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
             .column(Column::auto())
             .column(Column::auto())
             .header(20.0, draw_header_row)
-            .body(|body| draw_body_rows(body, chip8_state));
+            .body(|body| draw_body_rows(body, chip8_state, values, before, editable));
     });
 }
 
@@ -41,19 +188,92 @@ fn draw_header_row(mut header: TableRow) {
     });
 }
 
-fn draw_body_rows(mut body: TableBody, chip8_state: &State) {
+fn draw_body_rows(
+    mut body: TableBody,
+    chip8_state: &mut State,
+    values: &Values,
+    before: Option<&Values>,
+    editable: bool,
+) {
     body.ui_mut()
         .style_mut()
         .text_styles
         .insert(Body, FontId::monospace(11.0));
-    for v in 0..chip8_state.v.len() {
+    for field in Field::all() {
+        let value = field.value(values);
+        let changed = before.is_some_and(|before| field.value(before) != value);
         body.row(14.0, |mut row| {
             row.col(|ui| {
-                ui.label(format!("V{v:01X}"));
+                ui.label(field.name());
             });
             row.col(|ui| {
-                ui.label(format!("0x{:02X}", chip8_state.v[v]));
+                let digits = if field.max() > 0xff { 4 } else { 2 };
+                if changed {
+                    ui.visuals_mut().override_text_color = Some(CHANGED_COLOR);
+                }
+                if editable && !matches!(field, Field::Sp) {
+                    let mut edited = value;
+                    let response = ui.add(
+                        egui::DragValue::new(&mut edited)
+                            .range(0..=field.max())
+                            .hexadecimal(digits, false, true)
+                            .prefix("0x"),
+                    );
+                    if response.changed() {
+                        field.set(chip8_state, edited);
+                    }
+                } else {
+                    ui.label(format!("0x{value:0digits$X}"));
+                }
             });
         });
     }
 }
+
+// Return addresses, innermost first, each with the CALL that pushed it.
+fn draw_stack(ui: &mut Ui, chip8_state: &State, values: &Values, before: Option<&Values>) {
+    ui.strong("Stack");
+    if values.stack.is_empty() {
+        ui.weak("empty");
+    }
+    for (depth, &address) in values.stack.iter().enumerate().rev() {
+        let call_site = address.wrapping_sub(2);
+        let call = chip8_state
+            .memory
+            .get(call_site as usize..)
+            .and_then(Op::decode)
+            .map(|op| {
+                let (instr, args) = op.disassemble();
+                format!("{instr} {args}")
+            })
+            .unwrap_or_default();
+        let text = format!("{depth:X}: ${address:03x}  {call}");
+        let changed = before.is_some_and(|before| before.stack.get(depth) != Some(&address));
+        if changed {
+            ui.colored_label(CHANGED_COLOR, egui::RichText::new(text).monospace());
+        } else {
+            ui.monospace(text);
+        }
+    }
+}
+
+fn draw_keypad(ui: &mut Ui, chip8_state: &State) {
+    ui.strong("Keypad");
+    egui::Grid::new("keypad state").show(ui, |ui| {
+        for row in KEYPAD_LAYOUT {
+            for key in row {
+                let text = egui::RichText::new(format!("{key:X}")).monospace();
+                if chip8_state.keyboard[key] {
+                    ui.label(
+                        text.strong()
+                            .background_color(CHANGED_COLOR)
+                            .color(Color32::BLACK),
+                    );
+                } else {
+                    ui.label(text);
+                }
+            }
+            ui.end_row();
+        }
+    });
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+
+use crate::chip8::{LoadStore, Platform, Quirks};
+use crate::ui::config_path;
+
+const FILE_NAME: &str = "roms.cfg";
+
+// How platforms and LoadStore are written in the file, platforms the same way the cli takes them.
+const PLATFORMS: [(Platform, &str); 3] = [
+    (Platform::Chip8, "chip8"),
+    (Platform::SuperChip, "schip"),
+    (Platform::XoChip, "xochip"),
+];
+const LOAD_STORES: [(LoadStore, &str); 3] = [
+    (LoadStore::IncrementByXPlusOne, "increment_by_x_plus_one"),
+    (LoadStore::IncrementByX, "increment_by_x"),
+    (LoadStore::Unchanged, "unchanged"),
+];
+
+// The platform and quirks picked for a rom.
+#[derive(Clone, Copy)]
+pub struct RomSettings {
+    pub platform: Platform,
+    pub quirks: Quirks,
+}
+
+// The settings saved for each rom, by rom hash. Empty if nothing's been saved yet.
+pub fn load() -> Result<HashMap<u64, RomSettings>, String> {
+    let Some(path) = config_path(FILE_NAME) else {
+        return Ok(HashMap::new());
+    };
+    match fs::read_to_string(&path) {
+        Ok(config) => parse(&config).map_err(|error| format!("{}: {error}", path.display())),
+        Err(_) => Ok(HashMap::new()),
+    }
+}
+
+pub fn save(settings: &HashMap<u64, RomSettings>) -> Result<(), String> {
+    let path = config_path(FILE_NAME).ok_or(String::from(
+        "There's nowhere to save the rom settings, HOME isn't set.",
+    ))?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, to_config(settings)))
+        .map_err(|error| format!("Couldn't save {}: {error}", path.display()))
+}
+
+// The quirks that are on or off, by name.
+fn flags(quirks: &mut Quirks) -> [(&'static str, &mut bool); 5] {
+    [
+        ("shift_uses_vy", &mut quirks.shift_uses_vy),
+        ("jump_uses_vx", &mut quirks.jump_uses_vx),
+        ("vf_reset", &mut quirks.vf_reset),
+        ("clip_sprites", &mut quirks.clip_sprites),
+        ("get_key_on_press", &mut quirks.get_key_on_press),
+    ]
+}
+
+// A line for each rom: its hash, platform and LoadStore, then the quirks that are on.
+fn to_config(settings: &HashMap<u64, RomSettings>) -> String {
+    let mut config =
+        String::from("# rom hash, platform, load/store quirk, then the other quirks on\n");
+    let mut hashes: Vec<_> = settings.keys().copied().collect();
+    hashes.sort_unstable();
+    for hash in hashes {
+        let RomSettings {
+            platform,
+            mut quirks,
+        } = settings[&hash];
+        let platform = PLATFORMS
+            .iter()
+            .find(|&&(known, _)| known == platform)
+            .unwrap()
+            .1;
+        let load_store = LOAD_STORES
+            .iter()
+            .find(|&&(known, _)| known == quirks.load_store)
+            .unwrap()
+            .1;
+        let _ = write!(config, "{hash:016x} {platform} {load_store}");
+        for (name, on) in flags(&mut quirks) {
+            if *on {
+                let _ = write!(config, " {name}");
+            }
+        }
+        config.push('\n');
+    }
+    config
+}
+
+fn parse(config: &str) -> Result<HashMap<u64, RomSettings>, String> {
+    let mut settings = HashMap::new();
+    for (number, line) in (1..).zip(config.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| format!("line {number}: {message}");
+        let mut words = line.split_whitespace();
+        let hash = words.next().unwrap_or_default();
+        let hash = u64::from_str_radix(hash, 16)
+            .map_err(|_| error(format!("\"{hash}\" isn't a rom hash")))?;
+        let platform = words.next().unwrap_or_default();
+        let platform = PLATFORMS
+            .into_iter()
+            .find(|&(_, name)| name == platform)
+            .ok_or_else(|| error(format!("unknown platform \"{platform}\"")))?
+            .0;
+        let load_store = words.next().unwrap_or_default();
+        let load_store = LOAD_STORES
+            .into_iter()
+            .find(|&(_, name)| name == load_store)
+            .ok_or_else(|| error(format!("unknown load/store quirk \"{load_store}\"")))?
+            .0;
+        let mut quirks = Quirks {
+            shift_uses_vy: false,
+            load_store,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            get_key_on_press: false,
+        };
+        for word in words {
+            let (_, on) = flags(&mut quirks)
+                .into_iter()
+                .find(|(name, _)| *name == word)
+                .ok_or_else(|| error(format!("unknown quirk \"{word}\"")))?;
+            *on = true;
+        }
+        settings.insert(hash, RomSettings { platform, quirks });
+    }
+    Ok(settings)
+}
@@ -0,0 +1,111 @@
+use egui::{Key, KeyboardShortcut, Modifiers};
+
+use crate::chip8;
+use crate::ui::gui::Gui;
+
+#[derive(Clone, Copy)]
+enum Command {
+    PauseResume,
+    Step,
+    StepFrame,
+    StepOver,
+    StepOut,
+    RunToCursor,
+}
+
+const COMMANDS: [(Command, KeyboardShortcut); 6] = [
+    (
+        Command::PauseResume,
+        KeyboardShortcut::new(Modifiers::NONE, Key::F5),
+    ),
+    (
+        Command::Step,
+        KeyboardShortcut::new(Modifiers::NONE, Key::F11),
+    ),
+    (
+        Command::StepFrame,
+        KeyboardShortcut::new(Modifiers::NONE, Key::F6),
+    ),
+    (
+        Command::StepOver,
+        KeyboardShortcut::new(Modifiers::NONE, Key::F10),
+    ),
+    (
+        Command::StepOut,
+        KeyboardShortcut::new(Modifiers::SHIFT, Key::F11),
+    ),
+    (
+        Command::RunToCursor,
+        KeyboardShortcut::new(Modifiers::CTRL, Key::F10),
+    ),
+];
+
+pub fn draw(ctx: &egui::Context, ui_state: &mut Gui, chip8_state: &chip8::State) {
+    // backwards so shift+F11 gets matched before F11 does
+    for (command, shortcut) in COMMANDS.into_iter().rev() {
+        if ctx.input_mut(|i| i.consume_shortcut(&shortcut))
+            && enabled(command, ui_state, chip8_state)
+        {
+            run(command, ui_state, chip8_state);
+        }
+    }
+
+    egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            for (command, shortcut) in COMMANDS {
+                let button = egui::Button::new(label(command, ui_state));
+                let tooltip = ctx.format_shortcut(&shortcut);
+                if ui
+                    .add_enabled(enabled(command, ui_state, chip8_state), button)
+                    .on_hover_text(tooltip)
+                    .clicked()
+                {
+                    run(command, ui_state, chip8_state);
+                }
+            }
+            if let Some(hit) = ui_state.debugger.hit() {
+                ui.label(format!("Stopped: {hit}"));
+            }
+        });
+    });
+}
+
+fn label(command: Command, ui_state: &Gui) -> &'static str {
+    match command {
+        Command::PauseResume if ui_state.debugger.paused() => "▶ Resume",
+        Command::PauseResume => "⏸ Pause",
+        Command::Step => "Step",
+        Command::StepFrame => "Step frame",
+        Command::StepOver => "Step over",
+        Command::StepOut => "Step out",
+        Command::RunToCursor => "Run to cursor",
+    }
+}
+
+// Steps are only taken from a paused cpu.
+fn enabled(command: Command, ui_state: &Gui, chip8_state: &chip8::State) -> bool {
+    let paused = ui_state.debugger.paused();
+    match command {
+        Command::PauseResume => true,
+        Command::Step | Command::StepFrame | Command::StepOver => paused,
+        Command::StepOut => paused && chip8_state.sp() > 0,
+        Command::RunToCursor => paused && ui_state.disassembler.selected.is_some(),
+    }
+}
+
+fn run(command: Command, ui_state: &mut Gui, chip8_state: &chip8::State) {
+    let debugger = &mut ui_state.debugger;
+    match command {
+        Command::PauseResume if debugger.paused() => debugger.resume(),
+        Command::PauseResume => debugger.pause(),
+        Command::Step => debugger.step_instruction(),
+        Command::StepFrame => debugger.step_frame(),
+        Command::StepOver => debugger.step_over(chip8_state),
+        Command::StepOut => debugger.step_out(chip8_state),
+        Command::RunToCursor => {
+            if let Some(address) = ui_state.disassembler.selected {
+                debugger.run_to(address);
+            }
+        }
+    }
+}
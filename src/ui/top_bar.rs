@@ -1,9 +1,9 @@
-use std::fs;
-
+use egui::{KeyboardShortcut, Modifiers};
 use native_dialog::DialogBuilder;
 
 use crate::chip8;
-use crate::ui::gui::Gui;
+use crate::chip8::{LoadStore, Platform, Preset};
+use crate::ui::gui::{Gui, MovieMode, REWIND_KEY, SAVE_SLOT_KEYS};
 
 pub fn draw(ctx: &egui::Context, ui_state: &mut Gui, chip8_state: &mut chip8::State) {
     egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
@@ -12,28 +12,242 @@ pub fn draw(ctx: &egui::Context, ui_state: &mut Gui, chip8_state: &mut chip8::St
                 if ui.button("Open...").clicked() {
                     let path = DialogBuilder::file()
                         .add_filter("CHIP-8 ROM", ["ch8"])
+                        .add_filter("Octo program", ["8o"])
                         .open_single_file()
                         .show()
                         .unwrap();
 
                     if let Some(path) = path {
-                        let rom = fs::read(path).expect("failed to read file");
-                        chip8_state.load_rom(&rom);
+                        ui_state.load_rom(chip8_state, &path);
                     }
                     ui.close_menu();
                 }
-            });
+                if ui.button("Export disassembly...").clicked() {
+                    let path = DialogBuilder::file()
+                        .add_filter("CHIP-8 assembly", ["asm"])
+                        .save_single_file()
+                        .show()
+                        .unwrap();
 
-            egui::menu::menu_button(ui, "Tools", |ui| {
-                if ui.button("Disassemble...").clicked() {
-                    ui_state.disassembler.open = true;
+                    if let Some(path) = path {
+                        ui_state.export_disassembly(chip8_state, &path);
+                    }
                     ui.close_menu();
                 }
-                if ui.button("Registers...").clicked() {
-                    ui_state.registers.open = true;
+                ui.separator();
+                draw_save_state_menu(ui, ui_state, chip8_state);
+                ui.separator();
+                draw_movie_menu(ui, ui_state, chip8_state);
+            });
+
+            egui::menu::menu_button(ui, "Emulation", |ui| {
+                ui.add(
+                    egui::Slider::new(&mut ui_state.cycles_per_frame, 1..=1000)
+                        .logarithmic(true)
+                        .text("Instructions per frame"),
+                );
+                let mut rewind_seconds = ui_state.rewind_seconds();
+                ui.add(
+                    egui::Slider::new(&mut rewind_seconds, 0..=120)
+                        .suffix(" s")
+                        .text("Rewind history"),
+                )
+                .on_hover_text(format!("Hold {} to rewind", REWIND_KEY.name()));
+                if rewind_seconds != ui_state.rewind_seconds() {
+                    ui_state.set_rewind_seconds(rewind_seconds);
+                }
+                ui.menu_button("Platform", |ui| {
+                    draw_platform_menu(ui, ui_state, chip8_state);
+                });
+                ui.menu_button("Quirks", |ui| draw_quirks_menu(ui, ui_state, chip8_state));
+                ui.separator();
+                if ui.button("Key bindings...").clicked() {
+                    ui_state.key_bindings.open = true;
                     ui.close_menu();
                 }
             });
+
+            egui::menu::menu_button(ui, "Audio", |ui| draw_audio_menu(ui, ui_state));
+
+            egui::menu::menu_button(ui, "Tools", |ui| {
+                ui.checkbox(&mut ui_state.disassembler.open, "Disassembler");
+                ui.checkbox(&mut ui_state.registers.open, "Registers");
+                ui.checkbox(&mut ui_state.memory.open, "Memory");
+                ui.checkbox(&mut ui_state.breakpoints.open, "Breakpoints");
+                ui.checkbox(&mut ui_state.keypad.open, "Keypad");
+            });
         });
     });
 }
+
+fn draw_save_state_menu(ui: &mut egui::Ui, ui_state: &mut Gui, chip8_state: &mut chip8::State) {
+    if ui.button("Save state...").clicked() {
+        let path = DialogBuilder::file()
+            .add_filter("chip8uwu save state", ["c8s"])
+            .save_single_file()
+            .show()
+            .unwrap();
+
+        if let Some(path) = path {
+            ui_state.save_state_file(chip8_state, &path);
+        }
+        ui.close_menu();
+    }
+    if ui.button("Load state...").clicked() {
+        let path = DialogBuilder::file()
+            .add_filter("chip8uwu save state", ["c8s"])
+            .open_single_file()
+            .show()
+            .unwrap();
+
+        if let Some(path) = path {
+            ui_state.load_state_file(chip8_state, &path);
+        }
+        ui.close_menu();
+    }
+
+    ui.menu_button("Quick save", |ui| {
+        for (slot, key) in SAVE_SLOT_KEYS.into_iter().enumerate() {
+            let shortcut = ui
+                .ctx()
+                .format_shortcut(&KeyboardShortcut::new(Modifiers::SHIFT, key));
+            if ui
+                .add(egui::Button::new(format!("Slot {}", slot + 1)).shortcut_text(shortcut))
+                .clicked()
+            {
+                ui_state.quick_save(chip8_state, slot);
+                ui.close_menu();
+            }
+        }
+    });
+    ui.menu_button("Quick load", |ui| {
+        for (slot, key) in SAVE_SLOT_KEYS.into_iter().enumerate() {
+            let shortcut = ui
+                .ctx()
+                .format_shortcut(&KeyboardShortcut::new(Modifiers::NONE, key));
+            let button = egui::Button::new(format!("Slot {}", slot + 1)).shortcut_text(shortcut);
+            if ui.add_enabled(ui_state.slot_saved(slot), button).clicked() {
+                ui_state.quick_load(chip8_state, slot);
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+fn draw_movie_menu(ui: &mut egui::Ui, ui_state: &mut Gui, chip8_state: &mut chip8::State) {
+    match ui_state.movie {
+        Some(MovieMode::Recording(_)) => {
+            if ui.button("Stop recording movie...").clicked() {
+                let path = DialogBuilder::file()
+                    .add_filter("chip8uwu movie", ["c8m"])
+                    .save_single_file()
+                    .show()
+                    .unwrap();
+
+                // keeps recording if the dialog's cancelled
+                if let Some(path) = path {
+                    ui_state.save_movie(&path);
+                }
+                ui.close_menu();
+            }
+        }
+        Some(MovieMode::Playing(_)) => {
+            if ui.button("Stop playing movie").clicked() {
+                ui_state.movie = None;
+                ui.close_menu();
+            }
+        }
+        None => {
+            if ui.button("Record movie").clicked() {
+                ui_state.record_movie(chip8_state);
+                ui.close_menu();
+            }
+            if ui.button("Play movie...").clicked() {
+                let path = DialogBuilder::file()
+                    .add_filter("chip8uwu movie", ["c8m"])
+                    .open_single_file()
+                    .show()
+                    .unwrap();
+
+                if let Some(path) = path {
+                    ui_state.play_movie(chip8_state, &path);
+                }
+                ui.close_menu();
+            }
+        }
+    }
+}
+
+fn draw_platform_menu(ui: &mut egui::Ui, ui_state: &mut Gui, chip8_state: &mut chip8::State) {
+    for platform in Platform::ALL {
+        if ui
+            .radio(chip8_state.platform() == platform, platform.name())
+            .clicked()
+        {
+            ui_state.set_platform(chip8_state, platform, chip8_state.quirks);
+        }
+    }
+}
+
+fn draw_quirks_menu(ui: &mut egui::Ui, ui_state: &mut Gui, chip8_state: &mut chip8::State) {
+    let mut quirks = chip8_state.quirks;
+    let current_preset = Preset::from_quirks(quirks);
+    for preset in Preset::ALL {
+        if ui
+            .radio(current_preset == Some(preset), preset.name())
+            .clicked()
+        {
+            quirks = preset.quirks();
+        }
+    }
+    ui.separator();
+
+    ui.checkbox(&mut quirks.shift_uses_vy, "Shifts use VY");
+    ui.checkbox(&mut quirks.jump_uses_vx, "Jump with offset uses VX");
+    ui.checkbox(&mut quirks.vf_reset, "Logic ops reset VF");
+    ui.checkbox(&mut quirks.clip_sprites, "Clip sprites");
+    ui.checkbox(&mut quirks.get_key_on_press, "Key wait ends on press");
+    ui.label("Load/store:");
+    ui.radio_value(
+        &mut quirks.load_store,
+        LoadStore::IncrementByXPlusOne,
+        "I += X + 1",
+    );
+    ui.radio_value(&mut quirks.load_store, LoadStore::IncrementByX, "I += X");
+    ui.radio_value(&mut quirks.load_store, LoadStore::Unchanged, "I unchanged");
+
+    if quirks != chip8_state.quirks {
+        ui_state.set_platform(chip8_state, chip8_state.platform(), quirks);
+    }
+}
+
+fn draw_audio_menu(ui: &mut egui::Ui, ui_state: &mut Gui) {
+    let settings = &mut ui_state.audio;
+    ui.checkbox(&mut settings.muted, "Mute");
+    ui.add(egui::Slider::new(&mut settings.volume, 0.0..=1.0).text("Volume"));
+    ui.add(
+        egui::Slider::new(&mut settings.pitch, 110.0..=1760.0)
+            .logarithmic(true)
+            .suffix(" Hz")
+            .text("Pitch"),
+    );
+    ui.separator();
+
+    if ui_state.recording.is_some() {
+        if ui.button("Stop recording").clicked() {
+            ui_state.stop_recording();
+            ui.close_menu();
+        }
+    } else if ui.button("Record to WAV...").clicked() {
+        let path = DialogBuilder::file()
+            .add_filter("WAV audio", ["wav"])
+            .save_single_file()
+            .show()
+            .unwrap();
+
+        if let Some(path) = path {
+            ui_state.start_recording(&path);
+        }
+        ui.close_menu();
+    }
+}